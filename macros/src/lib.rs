@@ -0,0 +1,321 @@
+//! Procedural macros for [linked-hash-map-rs](https://docs.rs/linked-hash-map-rs).
+//!
+//! Use them through the `macros` feature of `linked-hash-map-rs` rather than depending on this
+//! crate directly: the generated code refers to `::linked_hash_map_rs`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::parse::Parser;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Error, FnArg, ItemFn, LitInt, LitStr, Pat, ReturnType, Type};
+
+/// Memoizes a function in a least-recently-used cache keyed by its arguments.
+///
+/// ```rust,ignore
+/// use linked_hash_map_rs::linked_cache;
+///
+/// #[linked_cache(capacity = 1000, ttl = "30s")]
+/// fn parse(input: &str) -> Ast {
+///     // ...
+/// }
+/// ```
+///
+/// Options:
+///
+/// * `capacity = N` (required): the maximum number of cached results.
+/// * `ttl = "..."`: how long a result stays valid, e.g. `"500ms"`, `"30s"`, `"5m"`, `"1h30m"`.
+///   Without it results only leave the cache through eviction.
+/// * `clock = Type`: the `linked_hash_map_rs::Clock` that expires results, built with
+///   `Default::default()`. Needs `ttl`; defaults to `SystemClock`.
+/// * `sync`: share one mutex-guarded cache between all threads instead of keeping one cache per
+///   thread. The lock is not held while the function body runs.
+///
+/// The return type must implement `Clone`. Arguments must be plain identifiers; owned arguments
+/// must implement `Clone + Hash + Eq`, and reference arguments `&T` are keyed by
+/// `<T as ToOwned>::Owned`, so `&str` is keyed by `String`. Generic functions, methods and
+/// `async fn` are not supported.
+#[proc_macro_attribute]
+pub fn linked_cache(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemFn);
+    let result = Options::parse(attr.into()).and_then(|options| expand(options, item));
+    match result {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Options {
+    capacity: usize,
+    ttl_millis: Option<u64>,
+    clock: Option<Type>,
+    sync: bool,
+}
+
+impl Options {
+    fn parse(attr: TokenStream2) -> syn::Result<Self> {
+        let mut capacity = None;
+        let mut ttl_millis = None;
+        let mut clock = None;
+        let mut sync = false;
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("capacity") {
+                // Only the value is kept, so a suffix like `1000u32` cannot clash with the
+                // `usize` the caches take.
+                let lit: LitInt = meta.value()?.parse()?;
+                capacity = Some(lit.base10_parse::<usize>()?);
+                Ok(())
+            } else if meta.path.is_ident("ttl") {
+                let lit: LitStr = meta.value()?.parse()?;
+                ttl_millis = Some(parse_duration_millis(&lit.value()).ok_or_else(|| {
+                    Error::new(
+                        lit.span(),
+                        "expected a duration like \"500ms\", \"30s\" or \"1h30m\"",
+                    )
+                })?);
+                Ok(())
+            } else if meta.path.is_ident("clock") {
+                clock = Some(meta.value()?.parse::<Type>()?);
+                Ok(())
+            } else if meta.path.is_ident("sync") {
+                sync = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `capacity`, `ttl`, `clock` or `sync`"))
+            }
+        });
+        parser.parse2(attr)?;
+        let capacity = capacity.ok_or_else(|| {
+            Error::new(
+                Span::call_site(),
+                "missing `capacity = N` in #[linked_cache]",
+            )
+        })?;
+        if let (Some(clock), None) = (&clock, ttl_millis) {
+            return Err(Error::new(clock.span(), "`clock` needs a `ttl`"));
+        }
+        Ok(Options {
+            capacity,
+            ttl_millis,
+            clock,
+            sync,
+        })
+    }
+}
+
+fn parse_duration_millis(s: &str) -> Option<u64> {
+    let mut total: u64 = 0;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return None;
+        }
+        let n: u64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "ms" => 1,
+            "s" => 1_000,
+            "m" => 60_000,
+            "h" => 3_600_000,
+            "d" => 86_400_000,
+            _ => return None,
+        };
+        total = total.checked_add(n.checked_mul(scale)?)?;
+        rest = &rest[unit_len..];
+    }
+    Some(total)
+}
+
+fn expand(options: Options, item: ItemFn) -> syn::Result<TokenStream2> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+
+    if let Some(asyncness) = sig.asyncness {
+        return Err(Error::new(
+            asyncness.span(),
+            "#[linked_cache] does not support async fn",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new(
+            sig.generics.span(),
+            "#[linked_cache] does not support generic functions",
+        ));
+    }
+    let ret = match &sig.output {
+        ReturnType::Type(_, ty) => ty.clone(),
+        ReturnType::Default => {
+            return Err(Error::new(
+                sig.span(),
+                "#[linked_cache] needs a return type",
+            ));
+        }
+    };
+
+    let mut args = Vec::new();
+    let mut key_types = Vec::new();
+    let mut key_exprs = Vec::new();
+    for input in &sig.inputs {
+        let typed = match input {
+            FnArg::Typed(typed) => typed,
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new(
+                    receiver.span(),
+                    "#[linked_cache] does not support methods",
+                ));
+            }
+        };
+        let ident = match &*typed.pat {
+            Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => &pat.ident,
+            pat => {
+                return Err(Error::new(
+                    pat.span(),
+                    "#[linked_cache] arguments must be plain identifiers",
+                ));
+            }
+        };
+        match &*typed.ty {
+            Type::Reference(reference) => {
+                let elem = &reference.elem;
+                key_types.push(quote!(<#elem as ::std::borrow::ToOwned>::Owned));
+                key_exprs.push(quote!(::std::borrow::ToOwned::to_owned(#ident)));
+            }
+            Type::ImplTrait(ty) => {
+                return Err(Error::new(
+                    ty.span(),
+                    "#[linked_cache] does not support `impl Trait` arguments",
+                ));
+            }
+            ty => {
+                key_types.push(quote!(#ty));
+                key_exprs.push(quote!(::std::clone::Clone::clone(&#ident)));
+            }
+        }
+        args.push(ident.clone());
+    }
+
+    let inner = format_ident!("__linked_cache_{}", sig.ident);
+    let mut inner_sig = sig.clone();
+    inner_sig.ident = inner.clone();
+    // The outer function only forwards its arguments, so `mut` bindings belong to the inner one.
+    let mut sig = sig;
+    for input in &mut sig.inputs {
+        if let FnArg::Typed(typed) = input {
+            if let Pat::Ident(pat) = &mut *typed.pat {
+                pat.mutability = None;
+            }
+        }
+    }
+
+    let key_ty = quote!((#(#key_types,)*));
+    let capacity = Literal::usize_unsuffixed(options.capacity);
+    let (cache_ty, cache_new) = match (options.ttl_millis, &options.clock) {
+        (Some(millis), None) => (
+            quote!(::linked_hash_map_rs::TtlCache<#key_ty, #ret>),
+            quote!(::linked_hash_map_rs::TtlCache::new(
+                #capacity,
+                ::std::time::Duration::from_millis(#millis),
+            )),
+        ),
+        (Some(millis), Some(clock)) => (
+            quote!(::linked_hash_map_rs::TtlCache<
+                #key_ty,
+                #ret,
+                ::std::collections::hash_map::RandomState,
+                #clock,
+            >),
+            quote!(::linked_hash_map_rs::TtlCache::with_clock(
+                #capacity,
+                ::std::time::Duration::from_millis(#millis),
+                <#clock as ::std::default::Default>::default(),
+            )),
+        ),
+        (None, _) => (
+            quote!(::linked_hash_map_rs::LruCache<#key_ty, #ret>),
+            quote!(::linked_hash_map_rs::LruCache::new(#capacity)),
+        ),
+    };
+
+    let lookup_and_store = if options.sync {
+        quote! {
+            static __LINKED_CACHE: ::std::sync::Mutex<::std::option::Option<#cache_ty>> =
+                ::std::sync::Mutex::new(::std::option::Option::None);
+            let __linked_cache_lock = || {
+                __LINKED_CACHE
+                    .lock()
+                    .unwrap_or_else(::std::sync::PoisonError::into_inner)
+            };
+            let __linked_cache_hit = __linked_cache_lock()
+                .get_or_insert_with(|| #cache_new)
+                .get(&__linked_cache_key)
+                .cloned();
+            if let ::std::option::Option::Some(__linked_cache_value) = __linked_cache_hit {
+                return __linked_cache_value;
+            }
+            let __linked_cache_value = #inner(#(#args),*);
+            __linked_cache_lock()
+                .get_or_insert_with(|| #cache_new)
+                .insert(__linked_cache_key, ::std::clone::Clone::clone(&__linked_cache_value));
+            __linked_cache_value
+        }
+    } else {
+        quote! {
+            ::std::thread_local! {
+                static __LINKED_CACHE: ::std::cell::RefCell<#cache_ty> =
+                    ::std::cell::RefCell::new(#cache_new);
+            }
+            let __linked_cache_hit = __LINKED_CACHE
+                .with(|cache| cache.borrow_mut().get(&__linked_cache_key).cloned());
+            if let ::std::option::Option::Some(__linked_cache_value) = __linked_cache_hit {
+                return __linked_cache_value;
+            }
+            let __linked_cache_value = #inner(#(#args),*);
+            __LINKED_CACHE.with(|cache| {
+                cache
+                    .borrow_mut()
+                    .insert(__linked_cache_key, ::std::clone::Clone::clone(&__linked_cache_value));
+            });
+            __linked_cache_value
+        }
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            #inner_sig #block
+
+            let __linked_cache_key: #key_ty = (#(#key_exprs,)*);
+            #lookup_and_store
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_duration_millis;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration_millis("500ms"), Some(500));
+        assert_eq!(parse_duration_millis("30s"), Some(30_000));
+        assert_eq!(parse_duration_millis("1h30m"), Some(5_400_000));
+        assert_eq!(parse_duration_millis("1d"), Some(86_400_000));
+        assert_eq!(parse_duration_millis(""), None);
+        assert_eq!(parse_duration_millis("30"), None);
+        assert_eq!(parse_duration_millis("s"), None);
+        assert_eq!(parse_duration_millis("3 weeks"), None);
+    }
+}
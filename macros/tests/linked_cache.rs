@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use linked_hash_map_rs::Clock;
+use linked_hash_map_rs_macros::linked_cache;
+
+static SQUARE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[linked_cache(capacity = 2)]
+fn square(x: u64) -> u64 {
+    SQUARE_CALLS.fetch_add(1, Ordering::SeqCst);
+    x * x
+}
+
+#[test]
+fn test_lru_eviction() {
+    assert_eq!(square(2), 4);
+    assert_eq!(square(3), 9);
+    assert_eq!(square(2), 4);
+    assert_eq!(SQUARE_CALLS.load(Ordering::SeqCst), 2);
+
+    // 3 is the least recently used entry and makes room for 4.
+    assert_eq!(square(4), 16);
+    assert_eq!(square(2), 4);
+    assert_eq!(SQUARE_CALLS.load(Ordering::SeqCst), 3);
+    assert_eq!(square(3), 9);
+    assert_eq!(SQUARE_CALLS.load(Ordering::SeqCst), 4);
+}
+
+#[linked_cache(capacity = 2u32)]
+fn suffixed_capacity(x: u64) -> u64 {
+    x + 1
+}
+
+#[test]
+fn test_suffixed_capacity() {
+    assert_eq!(suffixed_capacity(1), 2);
+    assert_eq!(suffixed_capacity(1), 2);
+}
+
+static LEN_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[linked_cache(capacity = 16)]
+fn word_len(word: &str, mut extra: usize) -> usize {
+    LEN_CALLS.fetch_add(1, Ordering::SeqCst);
+    extra += word.len();
+    extra
+}
+
+#[test]
+fn test_reference_arguments() {
+    assert_eq!(word_len("abc", 1), 4);
+    assert_eq!(word_len(&String::from("abc"), 1), 4);
+    assert_eq!(word_len("abc", 2), 5);
+    assert_eq!(LEN_CALLS.load(Ordering::SeqCst), 2);
+}
+
+/// Time as seen by `expiring`, moved forward by hand.
+static ELAPSED_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Default)]
+struct TestClock;
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        static START: OnceLock<Instant> = OnceLock::new();
+        *START.get_or_init(Instant::now)
+            + Duration::from_millis(ELAPSED_MILLIS.load(Ordering::SeqCst))
+    }
+}
+
+static TTL_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[linked_cache(capacity = 4, ttl = "30s", clock = TestClock)]
+fn expiring(x: u8) -> u8 {
+    TTL_CALLS.fetch_add(1, Ordering::SeqCst);
+    x
+}
+
+#[test]
+fn test_ttl() {
+    expiring(1);
+    ELAPSED_MILLIS.fetch_add(29_999, Ordering::SeqCst);
+    expiring(1);
+    assert_eq!(TTL_CALLS.load(Ordering::SeqCst), 1);
+    ELAPSED_MILLIS.fetch_add(1, Ordering::SeqCst);
+    expiring(1);
+    assert_eq!(TTL_CALLS.load(Ordering::SeqCst), 2);
+}
+
+static SHARED_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[linked_cache(capacity = 8, sync)]
+fn shared(x: u32) -> String {
+    SHARED_CALLS.fetch_add(1, Ordering::SeqCst);
+    x.to_string()
+}
+
+#[test]
+fn test_sync_cache_is_shared_between_threads() {
+    assert_eq!(shared(7), "7");
+    thread::spawn(|| assert_eq!(shared(7), "7")).join().unwrap();
+    assert_eq!(SHARED_CALLS.load(Ordering::SeqCst), 1);
+}
+
+static LOCAL_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[linked_cache(capacity = 8)]
+fn local(x: u32) -> u32 {
+    LOCAL_CALLS.fetch_add(1, Ordering::SeqCst);
+    x
+}
+
+#[test]
+fn test_thread_local_cache_per_thread() {
+    local(7);
+    thread::spawn(|| local(7)).join().unwrap();
+    assert_eq!(LOCAL_CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[linked_cache(capacity = 128)]
+fn fib(n: u64) -> u64 {
+    if n < 2 {
+        n
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+}
+
+#[linked_cache(capacity = 128, sync)]
+fn fib_sync(n: u64) -> u64 {
+    if n < 2 {
+        n
+    } else {
+        fib_sync(n - 1) + fib_sync(n - 2)
+    }
+}
+
+#[test]
+fn test_recursion() {
+    assert_eq!(fib(80), 23_416_728_348_467_685);
+    assert_eq!(fib_sync(80), 23_416_728_348_467_685);
+}
@@ -0,0 +1,275 @@
+//! A compact binary snapshot format for [LinkedHashMap] that needs no serialization crate.
+//!
+//! A snapshot is laid out as:
+//!
+//! | bytes    | content                                              |
+//! |----------|------------------------------------------------------|
+//! | 4        | magic `LHMC`                                         |
+//! | 1        | format version                                       |
+//! | varint   | entry count                                          |
+//! | ...      | each key then its value, front to back, via [Codec]  |
+//! | 4        | CRC-32 of everything before it, little endian        |
+//!
+//! Fixed-size values are little endian; lengths and the entry count are LEB128 varints.
+
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash};
+use std::io;
+
+use crate::LinkedHashMap;
+
+const MAGIC: &[u8; 4] = b"LHMC";
+const VERSION: u8 = 1;
+const CRC_LEN: usize = 4;
+
+/// A type that can be written to and read back from a [LinkedHashMap] snapshot.
+///
+/// `decode` advances `input` past the bytes it read. Implement it for your own keys and values
+/// by encoding their fields in order with the provided impls.
+pub trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self>;
+}
+
+macro_rules! impl_codec_for_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Codec for $ty {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(input: &mut &[u8]) -> io::Result<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$ty>()];
+                    bytes.copy_from_slice(take(input, std::mem::size_of::<$ty>())?);
+                    Ok(<$ty>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/// Written as a `u64`, so snapshots move between 32 and 64 bit targets.
+impl Codec for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        usize::try_from(u64::decode(input)?)
+            .map_err(|_| invalid_data("usize out of range for this target"))
+    }
+}
+
+/// Written as an `i64`, so snapshots move between 32 and 64 bit targets.
+impl Codec for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        isize::try_from(i64::decode(input)?)
+            .map_err(|_| invalid_data("isize out of range for this target"))
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8)
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid bool")),
+        }
+    }
+}
+
+impl Codec for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u32).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        char::from_u32(u32::decode(input)?).ok_or_else(|| invalid_data("invalid char"))
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.len() as u64);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        String::from_utf8(Vec::<u8>::decode(input)?).map_err(|_| invalid_data("invalid UTF-8"))
+    }
+}
+
+impl Codec for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.len() as u64);
+        out.extend_from_slice(self);
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        let len = read_len(input)?;
+        Ok(take(input, len)?.to_vec())
+    }
+}
+
+impl<K, V, S> LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Encodes every entry, front to back, into a checksummed snapshot.
+    pub fn to_bytes(&self) -> Vec<u8>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        write_varint(&mut out, self.len() as u64);
+        for (k, v) in self.iter() {
+            k.encode(&mut out);
+            v.encode(&mut out);
+        }
+        let crc = crc32(&out);
+        crc.encode(&mut out);
+        out
+    }
+
+    /// Decodes a snapshot written by [to_bytes](LinkedHashMap::to_bytes), in the same order.
+    ///
+    /// Fails with [InvalidData](io::ErrorKind::InvalidData) if the checksum does not match, the
+    /// snapshot has trailing bytes or repeats a key.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self>
+    where
+        K: Codec,
+        V: Codec,
+        S: Default,
+    {
+        LinkedHashMap::from_bytes_with_hasher(bytes, S::default())
+    }
+
+    pub fn from_bytes_with_hasher(bytes: &[u8], hasher: S) -> io::Result<Self>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        if bytes.len() < MAGIC.len() + 1 + CRC_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a linked-hash-map-rs snapshot"));
+        }
+        let (body, mut crc) = bytes.split_at(bytes.len() - CRC_LEN);
+        if u32::decode(&mut crc)? != crc32(body) {
+            return Err(invalid_data("snapshot checksum mismatch"));
+        }
+        let mut input = &body[MAGIC.len()..];
+        let version = u8::decode(&mut input)?;
+        if version != VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+        let len = read_varint(&mut input)?;
+        // Trust the count only as far as the bytes left could back it.
+        let capacity = usize::try_from(len).map_or(input.len(), |len| len.min(input.len()));
+        let mut map = LinkedHashMap::with_capacity_and_hasher(capacity, hasher);
+        for read in 0..len {
+            let k = K::decode(&mut input)?;
+            let v = V::decode(&mut input)?;
+            map.insert(k, v);
+            if map.len() as u64 == read {
+                return Err(invalid_data("snapshot repeats a key"));
+            }
+        }
+        if !input.is_empty() {
+            return Err(invalid_data("trailing bytes after the last entry"));
+        }
+        Ok(map)
+    }
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(invalid_data("unexpected end of snapshot"));
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> io::Result<u64> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = u8::decode(input)?;
+        // The tenth byte holds only the top bit; anything more would not fit.
+        if shift == 63 && byte > 1 {
+            return Err(invalid_data("varint longer than 64 bits"));
+        }
+        n |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(invalid_data("varint longer than 64 bits"))
+}
+
+/// Reads a length, rejecting one larger than the bytes left so a bad length cannot make us
+/// allocate more than the snapshot itself.
+fn read_len(input: &mut &[u8]) -> io::Result<usize> {
+    match usize::try_from(read_varint(input)?) {
+        Ok(len) if len <= input.len() => Ok(len),
+        _ => Err(invalid_data("length exceeds the snapshot")),
+    }
+}
+
+/// CRC-32 (IEEE 802.3), the one used by zlib and PNG.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,205 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+
+use crate::buffer::AccessBuffer;
+use crate::{CacheFullError, LruCache};
+
+const READ_BUFFER_SIZE: usize = 64;
+
+/// A thread-safe LRU cache for read-mostly workloads.
+///
+/// Lookups only take a shared lock. Instead of promoting the entry they hit, they record its
+/// key in a small lock-free buffer picked by the calling thread, and the recorded promotions are
+/// replayed in one batch the next time the write lock is taken: by `insert`, `remove`, `clear`,
+/// [apply_buffered_reads](ConcurrentLru::apply_buffered_reads), or by a reader that finds its
+/// buffer full. When a full buffer cannot be drained without blocking the access is dropped, so
+/// the recency order is approximate under heavy contention.
+///
+/// ```rust
+/// use linked_hash_map_rs::ConcurrentLru;
+///
+/// let cache = ConcurrentLru::new(2);
+/// cache.insert(1, "a");
+/// cache.insert(2, "b");
+/// assert_eq!(cache.get(&1), Some("a"));
+/// assert_eq!(cache.insert(3, "c"), Some((2, "b")));
+/// ```
+pub struct ConcurrentLru<K, V, S = RandomState> {
+    cache: RwLock<LruCache<K, V, S>>,
+    buffers: Box<[AccessBuffer<K>]>,
+}
+
+impl<K, V> ConcurrentLru<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new(capacity: usize) -> Self {
+        ConcurrentLru::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> ConcurrentLru<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        let stripes = thread::available_parallelism()
+            .map_or(4, |n| n.get())
+            .next_power_of_two();
+        ConcurrentLru {
+            cache: RwLock::new(LruCache::with_hasher(capacity, hasher)),
+            buffers: (0..stripes)
+                .map(|_| AccessBuffer::new(READ_BUFFER_SIZE))
+                .collect(),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, LruCache<K, V, S>> {
+        self.cache.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes the write lock and replays the buffered reads before handing it out.
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, LruCache<K, V, S>> {
+        let mut cache = self.cache.write().unwrap_or_else(PoisonError::into_inner);
+        self.drain(&mut cache);
+        cache
+    }
+
+    fn drain(&self, cache: &mut LruCache<K, V, S>) {
+        for buffer in self.buffers.iter() {
+            while let Some(key) = buffer.pop() {
+                cache.get(&key);
+            }
+        }
+    }
+
+    /// Calls `listener` with every entry evicted to make room.
+    pub fn set_eviction_listener<F>(&self, listener: F)
+    where
+        F: FnMut(&K, &V) + Send + Sync + 'static,
+    {
+        self.write().set_eviction_listener(listener)
+    }
+
+    /// Changes the capacity, evicting least recently used entries until the cache fits.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.write().set_capacity(capacity)
+    }
+
+    pub fn shrink_to_fit(&self) {
+        self.write().shrink_to_fit()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.read().capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.read().contains(key)
+    }
+
+    /// Returns a clone of the value for `key` and records the access.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q> + Clone,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        let cache = self.read();
+        let (key, value) = cache
+            .peek_key_value(key)
+            .map(|(k, v)| (k.clone(), v.clone()))?;
+        let buffer = &self.buffers[stripe() & (self.buffers.len() - 1)];
+        if let Err(key) = buffer.push(key) {
+            drop(cache);
+            if let Ok(mut cache) = self.cache.try_write() {
+                self.drain(&mut cache);
+                cache.get::<K>(&key);
+            }
+        }
+        Some(value)
+    }
+
+    /// Returns a clone of the value for `key` without recording the access.
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.read().peek(key).cloned()
+    }
+
+    /// Inserts `value` as the most recently used entry, returning the evicted entry, if any.
+    pub fn insert(&self, key: K, value: V) -> Option<(K, V)> {
+        self.write().insert(key, value)
+    }
+
+    /// Like [insert](ConcurrentLru::insert), but fails if the cache is full and every entry is
+    /// pinned.
+    pub fn try_insert(&self, key: K, value: V) -> Result<Option<(K, V)>, CacheFullError<K, V>> {
+        self.write().try_insert(key, value)
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write().remove(key)
+    }
+
+    /// Protects `key` from eviction. Returns whether the key is cached.
+    pub fn pin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write().pin(key)
+    }
+
+    /// Makes `key` evictable again. Returns whether the key is cached.
+    pub fn unpin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write().unpin(key)
+    }
+
+    pub fn clear(&self) {
+        self.write().clear()
+    }
+
+    /// Replays all buffered reads against the recency order now.
+    pub fn apply_buffered_reads(&self) {
+        drop(self.write());
+    }
+}
+
+/// Index of the calling thread, handed out in the order threads first read from any cache.
+fn stripe() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static STRIPE: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    STRIPE.with(|stripe| *stripe)
+}
@@ -0,0 +1,320 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{Iter, LinkedHashMap};
+
+/// A Lamport timestamp. Ties between replicas are broken by replica id, so stamps are totally
+/// ordered and unique.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Stamp {
+    counter: u64,
+    replica: u64,
+}
+
+/// A position in the order: the key placed right after `parent`, or at the front.
+#[derive(Clone)]
+struct Element<K> {
+    parent: Option<Stamp>,
+    key: K,
+}
+
+/// Last-writer-wins registers for a key's value and position. A removed key keeps its entry
+/// with no value, so a concurrent older write cannot bring it back.
+#[derive(Clone)]
+struct Register<V> {
+    value: Option<V>,
+    written: Stamp,
+    /// The element the key currently sits at.
+    position: Stamp,
+}
+
+/// An ordered map that replicas edit independently and [merge](ReplicatedLinkedHashMap::merge)
+/// in any order, arriving at the same contents and order.
+///
+/// Values are last-writer-wins registers. The order is an RGA sequence: every placement of a
+/// key, by an insert or a move, adds an element right after another one, and concurrent
+/// elements after the same one are ordered newest first. A key's latest placement wins, and
+/// the elements it left behind stay as anchors for others. Merging is commutative, associative
+/// and idempotent.
+///
+/// The current contents are kept as a [LinkedHashMap]. Local edits update it in place, while
+/// [merge](ReplicatedLinkedHashMap::merge) rebuilds it in time linear in the number of
+/// placements ever made. Removed keys and abandoned placements are never collected, so the
+/// state keeps growing with every edit even when the map itself does not.
+///
+/// ```rust
+/// use linked_hash_map_rs::ReplicatedLinkedHashMap;
+///
+/// let mut alice = ReplicatedLinkedHashMap::new(1);
+/// alice.insert("milk", 1);
+/// alice.insert("eggs", 12);
+/// let mut bob = alice.clone_for(2);
+///
+/// alice.move_to_front(&"eggs");
+/// bob.insert("milk", 2);
+/// bob.insert("bread", 1);
+///
+/// alice.merge(&bob);
+/// bob.merge(&alice);
+/// assert_eq!(alice.as_map(), bob.as_map());
+/// assert_eq!(
+///     alice.iter().collect::<Vec<_>>(),
+///     vec![(&"eggs", &12), (&"milk", &2), (&"bread", &1)]
+/// );
+/// ```
+pub struct ReplicatedLinkedHashMap<K, V> {
+    replica: u64,
+    clock: u64,
+    registers: HashMap<K, Register<V>>,
+    elements: HashMap<Stamp, Element<K>>,
+    view: LinkedHashMap<K, V>,
+}
+
+impl<K, V> ReplicatedLinkedHashMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    /// Creates an empty replica. Every replica editing the same map needs its own `replica` id.
+    pub fn new(replica: u64) -> Self {
+        ReplicatedLinkedHashMap {
+            replica,
+            clock: 0,
+            registers: HashMap::new(),
+            elements: HashMap::new(),
+            view: LinkedHashMap::new(),
+        }
+    }
+
+    /// Returns a copy of this replica's state under a new `replica` id, to start another
+    /// replica from.
+    pub fn clone_for(&self, replica: u64) -> Self {
+        let mut copy = ReplicatedLinkedHashMap::new(replica);
+        copy.merge(self);
+        copy
+    }
+
+    #[inline]
+    pub fn replica(&self) -> u64 {
+        self.replica
+    }
+
+    /// The current contents, in order.
+    #[inline]
+    pub fn as_map(&self) -> &LinkedHashMap<K, V> {
+        &self.view
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.view.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.view.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.view.get(key)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.view.contains(key)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.view.iter()
+    }
+
+    /// Sets the value for `key`. A new key goes to the back; an existing one keeps its place.
+    pub fn insert(&mut self, key: K, value: V) {
+        let stamp = self.tick();
+        match self.registers.get_mut(&key) {
+            Some(register) if register.value.is_some() => {
+                register.value = Some(value.clone());
+                register.written = stamp;
+                *self.view.get_mut(&key).expect("a live key is in the view") = value;
+            }
+            _ => {
+                // The new element is the newest child of the back key's, so it sorts right
+                // after it.
+                let parent = self.position_of_back();
+                self.elements.insert(
+                    stamp,
+                    Element {
+                        parent,
+                        key: key.clone(),
+                    },
+                );
+                self.registers.insert(
+                    key.clone(),
+                    Register {
+                        value: Some(value.clone()),
+                        written: stamp,
+                        position: stamp,
+                    },
+                );
+                self.view.push_back(key, value);
+            }
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.view.contains(key) {
+            return None;
+        }
+        let stamp = self.tick();
+        let register = self
+            .registers
+            .get_mut(key)
+            .expect("a live key has a register");
+        register.written = stamp;
+        register.value = None;
+        self.view.remove(key).map(|(_, v)| v)
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.place(key, None) {
+            return false;
+        }
+        self.view.move_to_front(key);
+        true
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let parent = self.position_of_back();
+        if !self.place(key, parent) {
+            return false;
+        }
+        self.view.move_to_back(key);
+        true
+    }
+
+    /// Moves `key` right after `anchor`. Returns false, changing nothing, if either key is
+    /// missing or they are the same.
+    pub fn move_after<Q>(&mut self, key: &Q, anchor: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if key == anchor || !self.view.contains(anchor) {
+            return false;
+        }
+        let parent = self.registers[anchor].position;
+        if !self.place(key, Some(parent)) {
+            return false;
+        }
+        self.view.move_after(key, Some(anchor));
+        true
+    }
+
+    /// Folds in every change `other` has seen. Both replicas end up equal once each has merged
+    /// the other.
+    pub fn merge(&mut self, other: &Self) {
+        self.clock = self.clock.max(other.clock);
+        for (id, element) in &other.elements {
+            self.elements.entry(*id).or_insert_with(|| element.clone());
+        }
+        for (key, theirs) in &other.registers {
+            match self.registers.get_mut(key) {
+                Some(ours) => {
+                    if theirs.written > ours.written {
+                        ours.value = theirs.value.clone();
+                        ours.written = theirs.written;
+                    }
+                    ours.position = ours.position.max(theirs.position);
+                }
+                None => {
+                    self.registers.insert(key.clone(), theirs.clone());
+                }
+            }
+        }
+        self.rebuild();
+    }
+
+    fn tick(&mut self) -> Stamp {
+        self.clock += 1;
+        Stamp {
+            counter: self.clock,
+            replica: self.replica,
+        }
+    }
+
+    fn position_of_back(&self) -> Option<Stamp> {
+        self.view
+            .back()
+            .map(|(key, _)| self.registers[key].position)
+    }
+
+    /// Adds a placement of `key` after `parent`, returning false if `key` is missing. A local
+    /// stamp is newer than every element seen so far, so the placement sorts right after
+    /// `parent` and the caller moves `key` there in the view.
+    fn place<Q>(&mut self, key: &Q, parent: Option<Stamp>) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let key = match self.view.get_key_value(key) {
+            Some((key, _)) => key.clone(),
+            None => return false,
+        };
+        let stamp = self.tick();
+        self.registers
+            .get_mut::<K>(&key)
+            .expect("a live key has a register")
+            .position = stamp;
+        self.elements.insert(stamp, Element { parent, key });
+        true
+    }
+
+    /// Walks the element tree depth first, newest child first, keeping each live key at its
+    /// current element.
+    fn rebuild(&mut self) {
+        let mut children: HashMap<Option<Stamp>, Vec<Stamp>> = HashMap::new();
+        for (id, element) in &self.elements {
+            children.entry(element.parent).or_default().push(*id);
+        }
+        for ids in children.values_mut() {
+            ids.sort_unstable();
+        }
+        let mut view = LinkedHashMap::with_capacity(self.registers.len());
+        // Children are sorted oldest first, so popping from the stack visits the newest first.
+        let mut stack = children.remove(&None).unwrap_or_default();
+        while let Some(id) = stack.pop() {
+            let key = &self.elements[&id].key;
+            let register = &self.registers[key];
+            if let (true, Some(value)) = (register.position == id, &register.value) {
+                view.push_back(key.clone(), value.clone());
+            }
+            if let Some(ids) = children.remove(&Some(id)) {
+                stack.extend(ids);
+            }
+        }
+        self.view = view;
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::LinkedHashMap;
+
+/// One step of the edit script returned by [LinkedHashMap::diff].
+///
+/// `after` names the key that the entry should follow, or `None` for the front.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit<K, V> {
+    Removed { key: K },
+    Added { key: K, value: V, after: Option<K> },
+    Changed { key: K, old: V, new: V },
+    Moved { key: K, after: Option<K> },
+}
+
+impl<K, V, S> LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns the edits that turn `self` into `other`, order included.
+    ///
+    /// Removals come first, then the remaining edits in `other`'s order. Keys on a longest
+    /// common subsequence of the two key orders stay put, so the script uses as few
+    /// [Moved](Edit::Moved) edits as possible.
+    ///
+    /// ```rust
+    /// use linked_hash_map_rs::{Edit, LinkedHashMap};
+    ///
+    /// let mut old = LinkedHashMap::new();
+    /// old.extend(vec![("a", 1), ("b", 2), ("c", 3)]);
+    /// let mut new = LinkedHashMap::new();
+    /// new.extend(vec![("c", 3), ("a", 1), ("b", 20)]);
+    /// let diff = old.diff(&new);
+    /// assert_eq!(
+    ///     diff,
+    ///     vec![
+    ///         Edit::Moved { key: "c", after: None },
+    ///         Edit::Changed { key: "b", old: 2, new: 20 },
+    ///     ]
+    /// );
+    ///
+    /// let mut patched = old.clone();
+    /// patched.apply_diff(diff);
+    /// assert_eq!(patched.iter().collect::<Vec<_>>(), new.iter().collect::<Vec<_>>());
+    /// ```
+    pub fn diff<T>(&self, other: &LinkedHashMap<K, V, T>) -> Vec<Edit<K, V>>
+    where
+        K: Clone,
+        V: Clone + PartialEq,
+        T: BuildHasher,
+    {
+        let positions = self
+            .iter()
+            .enumerate()
+            .map(|(i, (k, _))| (k, i))
+            .collect::<HashMap<_, _>>();
+        let mut edits = self
+            .iter()
+            .filter(|(k, _)| !other.contains(*k))
+            .map(|(k, _)| Edit::Removed { key: k.clone() })
+            .collect::<Vec<_>>();
+
+        let shared = other
+            .iter()
+            .filter_map(|(k, _)| positions.get(k).copied())
+            .collect::<Vec<_>>();
+        let mut stable = longest_increasing(&shared).into_iter();
+
+        let mut after: Option<&K> = None;
+        for (key, value) in other.iter() {
+            match self.get(key) {
+                None => edits.push(Edit::Added {
+                    key: key.clone(),
+                    value: value.clone(),
+                    after: after.cloned(),
+                }),
+                Some(old) => {
+                    if !stable.next().expect("one flag per shared key") {
+                        edits.push(Edit::Moved {
+                            key: key.clone(),
+                            after: after.cloned(),
+                        });
+                    }
+                    if old != value {
+                        edits.push(Edit::Changed {
+                            key: key.clone(),
+                            old: old.clone(),
+                            new: value.clone(),
+                        });
+                    }
+                }
+            }
+            after = Some(key);
+        }
+        edits
+    }
+
+    /// Applies an edit script from [diff](LinkedHashMap::diff) in order.
+    ///
+    /// Edits for keys that are missing are skipped, and an entry whose `after` key is missing
+    /// is left at the back.
+    pub fn apply_diff<I>(&mut self, edits: I)
+    where
+        I: IntoIterator<Item = Edit<K, V>>,
+    {
+        for edit in edits {
+            match edit {
+                Edit::Removed { key } => {
+                    self.remove(&key);
+                }
+                Edit::Added { key, value, after } => {
+                    let anchor = after.as_ref().filter(|after| self.contains(*after));
+                    if anchor.is_some() || after.is_none() {
+                        self.push_after(key, value, anchor);
+                    } else {
+                        self.remove(&key);
+                        self.push_back(key, value);
+                    }
+                }
+                Edit::Changed { key, new, .. } => {
+                    if let Some(value) = self.get_mut(&key) {
+                        *value = new;
+                    }
+                }
+                Edit::Moved { key, after } => {
+                    if !self.move_after(&key, after.as_ref()) {
+                        self.move_to_back(&key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Flags the elements of a longest strictly increasing subsequence of `seq`.
+pub(crate) fn longest_increasing(seq: &[usize]) -> Vec<bool> {
+    // tails[l] is the index of the smallest last element of an increasing run of length l + 1.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev = vec![None; seq.len()];
+    for (i, &x) in seq.iter().enumerate() {
+        let l = tails.partition_point(|&t| seq[t] < x);
+        if l > 0 {
+            prev[i] = Some(tails[l - 1]);
+        }
+        if l == tails.len() {
+            tails.push(i);
+        } else {
+            tails[l] = i;
+        }
+    }
+    let mut flags = vec![false; seq.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        flags[i] = true;
+        next = prev[i];
+    }
+    flags
+}
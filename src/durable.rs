@@ -0,0 +1,457 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::codec::crc32;
+use crate::{Codec, Iter, LinkedHashMap};
+
+const INSERT: u8 = 0;
+const REMOVE: u8 = 1;
+const MOVE_TO_FRONT: u8 = 2;
+const MOVE_TO_BACK: u8 = 3;
+const POP_FRONT: u8 = 4;
+const CLEAR: u8 = 5;
+const SNAPSHOT: u8 = 6;
+
+/// Payload length, payload CRC-32 and a CRC-32 of those two, all `u32` little endian. The
+/// header checksum tells a damaged length apart from a record cut short by a crash.
+const RECORD_HEADER_LEN: usize = 12;
+
+/// A [LinkedHashMap] that appends every mutation to a log file, so its contents and order
+/// survive a crash.
+///
+/// Keys and values are encoded with [Codec]. Each record is written to the operating system
+/// before the mutation returns; call [sync](DurableLinkedHashMap::sync) to also survive power
+/// loss. [open](DurableLinkedHashMap::open) replays the log, dropping a record torn by a crash
+/// mid-append.
+///
+/// The log grows with every mutation until [compact](DurableLinkedHashMap::compact) replaces
+/// it with a single snapshot; [set_compact_after](DurableLinkedHashMap::set_compact_after)
+/// makes that happen periodically. A failed periodic compaction does not fail the mutation
+/// that triggered it: the log stays as it was, the next mutation tries again, and
+/// [take_compact_error](DurableLinkedHashMap::take_compact_error) returns the error.
+///
+/// ```rust
+/// use linked_hash_map_rs::DurableLinkedHashMap;
+///
+/// let path = std::env::temp_dir().join("linked-hash-map-rs-doc-durable");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut jobs = DurableLinkedHashMap::open(&path).unwrap();
+/// jobs.insert(1u64, "resize".to_string()).unwrap();
+/// jobs.insert(2, "encode".to_string()).unwrap();
+/// jobs.move_to_front(&2).unwrap();
+/// drop(jobs);
+///
+/// let jobs = DurableLinkedHashMap::<u64, String>::open(&path).unwrap();
+/// assert_eq!(jobs.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![2, 1]);
+/// # std::fs::remove_file(path).unwrap();
+/// ```
+pub struct DurableLinkedHashMap<K, V, S = RandomState> {
+    map: LinkedHashMap<K, V, S>,
+    path: PathBuf,
+    file: File,
+    /// Length of the log in bytes, so appends need not ask the file system.
+    len: u64,
+    records: usize,
+    compact_after: Option<usize>,
+    compact_error: Option<io::Error>,
+}
+
+impl<K, V> DurableLinkedHashMap<K, V>
+where
+    K: Hash + Eq + Codec,
+    V: Codec,
+{
+    /// Opens the log at `path`, creating it if missing, and replays it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        DurableLinkedHashMap::open_with_hasher(path, RandomState::new())
+    }
+}
+
+impl<K, V, S> DurableLinkedHashMap<K, V, S>
+where
+    K: Hash + Eq + Codec,
+    V: Codec,
+    S: BuildHasher,
+{
+    /// Fails with [InvalidData](io::ErrorKind::InvalidData) if a record other than the last is
+    /// damaged or does not decode as `K` and `V`.
+    pub fn open_with_hasher<P: AsRef<Path>>(path: P, hasher: S) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut hasher = Some(hasher);
+        let mut map = None;
+        let mut records = 0;
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let payload = match read_record(&bytes[offset..])? {
+                Some(payload) => payload,
+                None => {
+                    // Torn by a crash mid-append: nothing after it was ever written.
+                    file.set_len(offset as u64)?;
+                    break;
+                }
+            };
+            if payload.first() == Some(&SNAPSHOT) {
+                if offset != 0 {
+                    return Err(invalid_data("snapshot record after the start of the log"));
+                }
+                let hasher = hasher.take().expect("the first record takes the hasher");
+                map = Some(LinkedHashMap::from_bytes_with_hasher(
+                    &payload[1..],
+                    hasher,
+                )?);
+            } else {
+                let map = map.get_or_insert_with(|| {
+                    LinkedHashMap::with_hasher(hasher.take().expect("the hasher is unused"))
+                });
+                replay(map, payload)?;
+                records += 1;
+            }
+            offset += RECORD_HEADER_LEN + payload.len();
+        }
+
+        Ok(DurableLinkedHashMap {
+            map: map.unwrap_or_else(|| {
+                LinkedHashMap::with_hasher(hasher.take().expect("the hasher is unused"))
+            }),
+            path,
+            file,
+            len: offset as u64,
+            records,
+            compact_after: None,
+            compact_error: None,
+        })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of records appended since the log was last compacted.
+    #[inline]
+    pub fn log_records(&self) -> usize {
+        self.records
+    }
+
+    /// Compacts the log whenever it holds more than `records` records.
+    pub fn set_compact_after(&mut self, records: usize) {
+        self.compact_after = Some(records);
+    }
+
+    /// Returns the error from the latest failed periodic compaction, if any, and forgets it.
+    pub fn take_compact_error(&mut self) -> Option<io::Error> {
+        self.compact_error.take()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains(key)
+    }
+
+    #[inline]
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.map.front()
+    }
+
+    #[inline]
+    pub fn back(&self) -> Option<(&K, &V)> {
+        self.map.back()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    /// Logs and applies an insert: a new key goes to the back, an existing one keeps its place
+    /// and takes the new value.
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<()> {
+        let mut payload = vec![INSERT];
+        key.encode(&mut payload);
+        value.encode(&mut payload);
+        self.append(&payload)?;
+        self.map.insert(key, value);
+        self.maybe_compact();
+        Ok(())
+    }
+
+    /// Removes `key`. Nothing is logged if it is missing.
+    pub fn remove<Q>(&mut self, key: &Q) -> io::Result<Option<(K, V)>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.log_key(REMOVE, key)? {
+            return Ok(None);
+        }
+        let removed = self.map.remove(key);
+        self.maybe_compact();
+        Ok(removed)
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.log_key(MOVE_TO_FRONT, key)? {
+            return Ok(false);
+        }
+        self.map.move_to_front(key);
+        self.maybe_compact();
+        Ok(true)
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.log_key(MOVE_TO_BACK, key)? {
+            return Ok(false);
+        }
+        self.map.move_to_back(key);
+        self.maybe_compact();
+        Ok(true)
+    }
+
+    pub fn pop_front(&mut self) -> io::Result<Option<(K, V)>> {
+        if self.map.is_empty() {
+            return Ok(None);
+        }
+        self.append(&[POP_FRONT])?;
+        let popped = self.map.pop_front();
+        self.maybe_compact();
+        Ok(popped)
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.append(&[CLEAR])?;
+        self.map.clear();
+        self.maybe_compact();
+        Ok(())
+    }
+
+    /// Flushes the log to disk, so the mutations so far also survive power loss.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Replaces the log with a single snapshot of the current contents.
+    ///
+    /// The snapshot is written next to the log and renamed over it, so a crash leaves either
+    /// the old log or the new one. On failure the snapshot file is removed and the log is left
+    /// as it was, unless only syncing the directory after the rename failed.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut payload = vec![SNAPSHOT];
+        payload.extend_from_slice(&self.map.to_bytes());
+        let snapshot = record(&payload)?;
+        let tmp = self.path.with_extension("compact");
+        let written = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .truncate(false)
+            .open(&tmp)
+            .and_then(|mut out| {
+                out.set_len(0)?;
+                out.write_all(&snapshot)?;
+                out.sync_all()?;
+                fs::rename(&tmp, &self.path)?;
+                Ok(out)
+            });
+        match written {
+            Ok(out) => {
+                self.file = out;
+                self.len = snapshot.len() as u64;
+                self.records = 0;
+                sync_dir(&self.path)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&tmp);
+                Err(err)
+            }
+        }
+    }
+
+    /// Logs `tag` followed by the stored copy of `key`, returning false without logging if the
+    /// key is missing.
+    fn log_key<Q>(&mut self, tag: u8, key: &Q) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut payload = vec![tag];
+        match self.map.get_key_value(key) {
+            Some((key, _)) => key.encode(&mut payload),
+            None => return Ok(false),
+        }
+        self.append(&payload)?;
+        Ok(true)
+    }
+
+    /// Appends a record, cutting the log back to its old length if the write fails partway so
+    /// that later records do not follow a damaged one.
+    fn append(&mut self, payload: &[u8]) -> io::Result<()> {
+        let record = record(payload)?;
+        if let Err(err) = self.file.write_all(&record) {
+            let _ = self.file.set_len(self.len);
+            return Err(err);
+        }
+        self.len += record.len() as u64;
+        self.records += 1;
+        Ok(())
+    }
+
+    fn maybe_compact(&mut self) {
+        match self.compact_after {
+            Some(after) if self.records > after => {
+                if let Err(err) = self.compact() {
+                    self.compact_error = Some(err);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Fails with [InvalidInput](io::ErrorKind::InvalidInput) if the payload is too long for the
+/// record header to hold its length.
+fn record(payload: &[u8]) -> io::Result<Vec<u8>> {
+    let len = u32::try_from(payload.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "record larger than 4 GiB can't be logged",
+        )
+    })?;
+    let mut out = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    len.encode(&mut out);
+    crc32(payload).encode(&mut out);
+    crc32(&out).encode(&mut out);
+    out.extend_from_slice(payload);
+    Ok(out)
+}
+
+/// Syncs the directory holding `path`, so a rename into it survives power loss.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Other platforms offer no portable way to sync a directory.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Returns the payload of the record at the start of `bytes`, or `None` if it was torn by a
+/// crash: its header is incomplete, or it is the last thing in the log and cut short or
+/// damaged.
+fn read_record(bytes: &[u8]) -> io::Result<Option<&[u8]>> {
+    if bytes.len() < RECORD_HEADER_LEN {
+        return Ok(None);
+    }
+    let (header, rest) = bytes.split_at(RECORD_HEADER_LEN);
+    let mut fields = header;
+    let len = u32::decode(&mut fields)? as usize;
+    let crc = u32::decode(&mut fields)?;
+    if crc32(&header[..8]) != u32::decode(&mut fields)? {
+        return if rest.is_empty() {
+            Ok(None)
+        } else {
+            Err(invalid_data(
+                "damaged record header in the middle of the log",
+            ))
+        };
+    }
+    if rest.len() < len {
+        return Ok(None);
+    }
+    let payload = &rest[..len];
+    if crc32(payload) == crc {
+        Ok(Some(payload))
+    } else if rest.len() == len {
+        Ok(None)
+    } else {
+        Err(invalid_data("damaged record in the middle of the log"))
+    }
+}
+
+fn replay<K, V, S>(map: &mut LinkedHashMap<K, V, S>, payload: &[u8]) -> io::Result<()>
+where
+    K: Hash + Eq + Codec,
+    V: Codec,
+    S: BuildHasher,
+{
+    let (&tag, mut input) = payload
+        .split_first()
+        .ok_or_else(|| invalid_data("empty record"))?;
+    match tag {
+        INSERT => {
+            let key = K::decode(&mut input)?;
+            let value = V::decode(&mut input)?;
+            map.insert(key, value);
+        }
+        REMOVE => {
+            map.remove(&K::decode(&mut input)?);
+        }
+        MOVE_TO_FRONT => {
+            map.move_to_front(&K::decode(&mut input)?);
+        }
+        MOVE_TO_BACK => {
+            map.move_to_back(&K::decode(&mut input)?);
+        }
+        POP_FRONT => {
+            map.pop_front();
+        }
+        CLEAR => map.clear(),
+        _ => return Err(invalid_data("unknown record")),
+    }
+    if !input.is_empty() {
+        return Err(invalid_data("trailing bytes in record"));
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
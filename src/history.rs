@@ -0,0 +1,420 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use crate::{Iter, LinkedHashMap};
+
+/// An edit to a map. Applying one returns the edit that reverts it.
+enum Op<K, V> {
+    Remove(K),
+    /// Puts a new key right after `after`, or at the front.
+    Insert {
+        key: K,
+        value: V,
+        after: Option<K>,
+    },
+    Set(K, V),
+    /// Moves a key right after `after`, or to the front.
+    Move {
+        key: K,
+        after: Option<K>,
+    },
+    /// Puts the keys in this order.
+    Reorder(Vec<K>),
+}
+
+/// The edits making up one step, applied last to first.
+type Step<K, V> = Vec<Op<K, V>>;
+
+/// A [LinkedHashMap] that can [undo](HistoryLinkedHashMap::undo) and
+/// [redo](HistoryLinkedHashMap::redo) its edits, order included.
+///
+/// Every edit records its inverse as a step. Only the latest `limit` steps are kept, and a new
+/// edit after an undo drops the steps that could have been redone. Edits made inside
+/// [group](HistoryLinkedHashMap::group) are undone and redone as one step.
+///
+/// ```rust
+/// use linked_hash_map_rs::HistoryLinkedHashMap;
+///
+/// let mut panel = HistoryLinkedHashMap::new(100);
+/// panel.insert("width", 10);
+/// panel.insert("height", 20);
+/// panel.group(|panel| {
+///     panel.insert("depth", 5);
+///     panel.sort_by_key(|_, value| *value);
+/// });
+/// let keys = |panel: &HistoryLinkedHashMap<&'static str, i32>| {
+///     panel.iter().map(|(k, _)| *k).collect::<Vec<_>>()
+/// };
+/// assert_eq!(keys(&panel), vec!["depth", "width", "height"]);
+///
+/// panel.undo();
+/// assert_eq!(keys(&panel), vec!["width", "height"]);
+/// panel.redo();
+/// assert_eq!(panel.len(), 3);
+/// ```
+pub struct HistoryLinkedHashMap<K, V, S = RandomState> {
+    map: LinkedHashMap<K, V, S>,
+    undo: VecDeque<Step<K, V>>,
+    redo: Vec<Step<K, V>>,
+    limit: usize,
+    /// The step being collected by [group](HistoryLinkedHashMap::group).
+    group: Option<Step<K, V>>,
+}
+
+impl<K, V> HistoryLinkedHashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    /// Creates an empty map keeping up to `limit` steps to undo.
+    pub fn new(limit: usize) -> Self {
+        HistoryLinkedHashMap::with_hasher(limit, RandomState::new())
+    }
+}
+
+impl<K, V, S> HistoryLinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn with_hasher(limit: usize, hasher: S) -> Self {
+        HistoryLinkedHashMap {
+            map: LinkedHashMap::with_hasher(hasher),
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+            group: None,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains(key)
+    }
+
+    #[inline]
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.map.front()
+    }
+
+    #[inline]
+    pub fn back(&self) -> Option<(&K, &V)> {
+        self.map.back()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    #[inline]
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets every step, keeping the contents.
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+impl<K, V, S> HistoryLinkedHashMap<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Pushes a new key to the back, or updates an existing one in place, returning the old
+    /// value.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(slot) = self.map.get_mut(&key) {
+            let old = mem::replace(slot, value);
+            self.record(Op::Set(key, old.clone()));
+            return Some(old);
+        }
+        self.record(Op::Remove(key.clone()));
+        self.map.push_back(key, value);
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let after = self.map.key_before(key).cloned();
+        let (key, value) = self.map.remove(key)?;
+        self.record(Op::Insert {
+            key: key.clone(),
+            value: value.clone(),
+            after,
+        });
+        Some((key, value))
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.move_after(key, None)
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let back = match self.map.back() {
+            Some((back, _)) if <K as Borrow<Q>>::borrow(back) != key => back.clone(),
+            Some(_) => return self.map.contains(key),
+            None => return false,
+        };
+        self.move_after(key, Some(<K as Borrow<Q>>::borrow(&back)))
+    }
+
+    /// Moves `key` right after `anchor`, or to the front if `anchor` is `None`. Returns false,
+    /// changing nothing, if either key is missing or they are the same.
+    pub fn move_after<Q>(&mut self, key: &Q, anchor: Option<&Q>) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if anchor.is_some_and(|anchor| anchor == key || !self.map.contains(anchor)) {
+            return false;
+        }
+        let stored = match self.map.get_key_value(key) {
+            Some((stored, _)) => stored.clone(),
+            None => return false,
+        };
+        let after = self.map.key_before(key).cloned();
+        if after.as_ref().map(<K as Borrow<Q>>::borrow) == anchor {
+            return true;
+        }
+        self.map.move_after(key, anchor);
+        self.record(Op::Move { key: stored, after });
+        true
+    }
+
+    /// Sorts the entries with `compare`, keeping equal entries in their order.
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut((&K, &V), (&K, &V)) -> Ordering,
+    {
+        let mut entries = self.map.iter().collect::<Vec<_>>();
+        entries.sort_by(|&a, &b| compare(a, b));
+        let order = entries
+            .into_iter()
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+        self.reorder(order);
+    }
+
+    /// Sorts the entries by the key `f` extracts, keeping equal entries in their order.
+    pub fn sort_by_key<T, F>(&mut self, mut f: F)
+    where
+        T: Ord,
+        F: FnMut(&K, &V) -> T,
+    {
+        self.sort_by(|(ak, av), (bk, bv)| f(ak, av).cmp(&f(bk, bv)));
+    }
+
+    /// Moves the listed keys to the front in the given order. Missing keys are skipped, and the
+    /// rest keep their order after them.
+    pub fn reorder<I>(&mut self, keys: I)
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let before = self.map.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>();
+        let mut listed = keys
+            .into_iter()
+            .filter(|k| self.map.contains(k))
+            .collect::<Vec<_>>();
+        let mut seen = HashSet::with_capacity(listed.len());
+        listed.retain(|k| seen.insert(k.clone()));
+        let order = listed
+            .into_iter()
+            .chain(before.iter().filter(|k| !seen.contains(*k)).cloned())
+            .collect::<Vec<_>>();
+        if order == before {
+            return;
+        }
+        arrange(&mut self.map, &order);
+        self.record(Op::Reorder(before));
+    }
+
+    /// Runs `edits` and records everything it changes as a single step. Groups nested inside it
+    /// join the outer one, and [undo](HistoryLinkedHashMap::undo) and
+    /// [redo](HistoryLinkedHashMap::redo) do nothing until it ends.
+    ///
+    /// If `edits` panics, the changes it made before panicking are still recorded as one step.
+    pub fn group<F, R>(&mut self, edits: F) -> R
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        if self.group.is_some() {
+            return edits(self);
+        }
+        self.group = Some(Vec::new());
+        let guard = GroupGuard { history: self };
+        edits(guard.history)
+    }
+
+    /// Reverts the latest step. Returns false if there is none or a
+    /// [group](HistoryLinkedHashMap::group) is open.
+    pub fn undo(&mut self) -> bool {
+        if self.group.is_some() {
+            return false;
+        }
+        match self.undo.pop_back() {
+            Some(step) => {
+                let inverse = self.replay(step);
+                self.redo.push(inverse);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapplies the latest undone step. Returns false if there is none or a
+    /// [group](HistoryLinkedHashMap::group) is open.
+    pub fn redo(&mut self) -> bool {
+        if self.group.is_some() {
+            return false;
+        }
+        match self.redo.pop() {
+            Some(step) => {
+                let inverse = self.replay(step);
+                self.undo.push_back(inverse);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn record(&mut self, op: Op<K, V>) {
+        match &mut self.group {
+            Some(step) => step.push(op),
+            None => self.push_undo(vec![op]),
+        }
+    }
+
+    fn push_undo(&mut self, step: Step<K, V>) {
+        self.redo.clear();
+        self.undo.push_back(step);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Applies the edits of `step` last to first, returning the step that reverts them. Its
+    /// edits come out in the order they were applied, so replaying it last to first is right too.
+    fn replay(&mut self, step: Step<K, V>) -> Step<K, V> {
+        step.into_iter()
+            .rev()
+            .map(|op| apply(&mut self.map, op))
+            .collect()
+    }
+}
+
+/// Closes the group opened by [group](HistoryLinkedHashMap::group) when dropped, even while
+/// unwinding.
+struct GroupGuard<'a, K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    history: &'a mut HistoryLinkedHashMap<K, V, S>,
+}
+
+impl<K, V, S> Drop for GroupGuard<'_, K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        if let Some(step) = self.history.group.take() {
+            if !step.is_empty() {
+                self.history.push_undo(step);
+            }
+        }
+    }
+}
+
+fn apply<K, V, S>(map: &mut LinkedHashMap<K, V, S>, op: Op<K, V>) -> Op<K, V>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher,
+{
+    match op {
+        Op::Remove(key) => {
+            let after = map.key_before(&key).cloned();
+            let (key, value) = map.remove(&key).expect("history matches the map");
+            Op::Insert { key, value, after }
+        }
+        Op::Insert { key, value, after } => {
+            map.push_after(key.clone(), value, after.as_ref());
+            Op::Remove(key)
+        }
+        Op::Set(key, value) => {
+            let slot = map.get_mut(&key).expect("history matches the map");
+            let old = mem::replace(slot, value);
+            Op::Set(key, old)
+        }
+        Op::Move { key, after } => {
+            let before = map.key_before(&key).cloned();
+            map.move_after(&key, after.as_ref());
+            Op::Move { key, after: before }
+        }
+        Op::Reorder(order) => {
+            let before = map.iter().map(|(k, _)| k.clone()).collect();
+            arrange(map, &order);
+            Op::Reorder(before)
+        }
+    }
+}
+
+/// Puts the keys of `map` in `order`, which lists each of them once.
+fn arrange<K, V, S>(map: &mut LinkedHashMap<K, V, S>, order: &[K])
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    for key in order {
+        map.move_to_back(key);
+    }
+}
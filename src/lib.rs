@@ -24,14 +24,54 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::marker::PhantomData;
 use std::ptr::replace;
 
+pub use codec::Codec;
+pub use concurrent::ConcurrentLru;
+pub use crdt::ReplicatedLinkedHashMap;
+pub use diff::Edit;
+pub use durable::DurableLinkedHashMap;
+pub use history::HistoryLinkedHashMap;
+pub use loading::{Loader, LoadingCache, NoStore, Store};
+pub use lru::{CacheFullError, LruCache};
+pub use merge::{MergeConflict, MergeOrder, MergeStrategy, ThreeWayMerge};
+pub use observed::{End, MapEvent, ObservedLinkedHashMap};
+pub use refresh::{DeferredExecutor, Executor, ImmediateExecutor, RefreshingCache};
+pub use sharded::{CacheStats, ShardedLru};
+#[cfg(feature = "tiered")]
+pub use tiered::TieredCache;
+pub use transaction::Transaction;
+pub use ttl::{Clock, ManualClock, SystemClock, TtlCache};
+pub use write_back::{FlushDirty, Flusher, MemoryStore, WriteBackCache};
+#[cfg(feature = "macros")]
+pub use linked_hash_map_rs_macros::linked_cache;
+
+mod buffer;
+mod codec;
+mod concurrent;
+mod crdt;
+mod diff;
+mod durable;
+mod history;
+mod loading;
+mod lru;
+mod merge;
+mod observed;
+#[cfg(feature = "persist")]
+mod persist;
+mod refresh;
 #[cfg(feature = "serde")]
-mod serde;
+pub mod serde;
+mod sharded;
 #[cfg(test)]
 mod tests;
+#[cfg(feature = "tiered")]
+mod tiered;
+mod transaction;
+mod ttl;
+mod write_back;
 
 struct KeyPtr<K> {
     k: *const K,
@@ -127,7 +167,13 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
             ..Default::default()
         }
     }
+}
 
+impl<K, V, S> LinkedHashMap<K, V, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+{
     #[inline]
     pub fn push_front(&mut self, key: K, value: V) -> Option<(&K, &V)> {
         unsafe {
@@ -165,7 +211,7 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     #[inline]
     pub fn pop_front_node(&mut self) -> Option<Box<Node<K, V>>> {
         self.head
-            .map(|node| unsafe {
+            .and_then(|node| unsafe {
                 self.head = (*node).next;
 
                 match self.head {
@@ -177,7 +223,6 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
                     .remove(&KeyPtr { k: &(*node).key })
                     .map(|node| Box::from_raw(node))
             })
-            .flatten()
     }
 
     #[inline]
@@ -227,7 +272,7 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     #[inline]
     pub fn pop_back_node(&mut self) -> Option<Box<Node<K, V>>> {
         self.tail
-            .map(|node| unsafe {
+            .and_then(|node| unsafe {
                 self.tail = (*node).prev;
 
                 match self.tail {
@@ -239,7 +284,6 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
                     .remove(&KeyPtr { k: &(*node).key })
                     .map(|node| Box::from_raw(node))
             })
-            .flatten()
     }
 
     #[inline]
@@ -264,10 +308,15 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     }
 
     #[inline]
-    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&V>
+    pub fn shrink_to_fit(&mut self) {
+        self.hash_map.shrink_to_fit()
+    }
+
+    #[inline]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
         where
             K: Borrow<Q>,
-            Q: Hash + Eq,
+            Q: Hash + Eq + ?Sized,
     {
         self.hash_map
             .get(Qey::from_ref(key))
@@ -275,10 +324,32 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     }
 
     #[inline]
-    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
         where
             K: Borrow<Q>,
-            Q: Hash + Eq,
+            Q: Hash + Eq + ?Sized,
+    {
+        self.hash_map
+            .get(Qey::from_ref(key))
+            .map(|node| unsafe { (&(**node).key, &(**node).value) })
+    }
+
+    #[inline]
+    pub fn get_key_value_mut<Q>(&mut self, key: &Q) -> Option<(&K, &mut V)>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+    {
+        self.hash_map
+            .get_mut(Qey::from_ref(key))
+            .map(|node| unsafe { (&(**node).key, &mut (**node).value) })
+    }
+
+    #[inline]
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
     {
         self.hash_map
             .get_mut(Qey::from_ref(key))
@@ -286,10 +357,10 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     }
 
     #[inline]
-    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
         where
             K: Borrow<Q>,
-            Q: Hash + Eq,
+            Q: Hash + Eq + ?Sized,
     {
         self.hash_map.remove(Qey::from_ref(key)).map(|node| unsafe {
             self.remove_node(node);
@@ -321,14 +392,14 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     }
 
     #[inline]
-    pub fn move_to_front<Q: ?Sized>(&mut self, key: &Q) -> Option<(&K, &V)>
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
         where
             K: Borrow<Q>,
-            Q: Hash + Eq,
+            Q: Hash + Eq + ?Sized,
     {
         self.hash_map
             .get(Qey::from_ref(key))
-            .map(|node| *node)
+            .copied()
             .map(|node| unsafe {
                 self.remove_node(node);
                 self.push_front_node(node);
@@ -337,14 +408,14 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     }
 
     #[inline]
-    pub fn move_to_back<Q: ?Sized>(&mut self, key: &Q) -> Option<(&K, &V)>
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
         where
             K: Borrow<Q>,
-            Q: Hash + Eq,
+            Q: Hash + Eq + ?Sized,
     {
         self.hash_map
             .get(Qey::from_ref(key))
-            .map(|node| *node)
+            .copied()
             .map(|node| unsafe {
                 self.remove_node(node);
                 self.push_back_node(node);
@@ -352,11 +423,114 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
             })
     }
 
+    /// Returns the key right before `key`, or `None` if `key` is at the front or missing.
+    pub(crate) fn key_before<Q>(&self, key: &Q) -> Option<&K>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+    {
+        self.hash_map
+            .get(Qey::from_ref(key))
+            .and_then(|&node| unsafe { (*node).prev.map(|prev| &(*prev).key) })
+    }
+
+    /// Moves `key` right after `anchor`, or to the front if `anchor` is `None`. Returns false,
+    /// changing nothing, if either key is missing or they are the same.
+    pub(crate) fn move_after<Q>(&mut self, key: &Q, anchor: Option<&Q>) -> bool
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+    {
+        match self.hash_map.get(Qey::from_ref(key)) {
+            Some(&node) => self.move_node_after(node, anchor),
+            None => false,
+        }
+    }
+
+    /// Inserts like [push_back](LinkedHashMap::push_back), then moves the entry right after
+    /// `anchor` as [move_after](LinkedHashMap::move_after) does. Returns whether it moved.
+    pub(crate) fn push_after<Q>(&mut self, key: K, value: V, anchor: Option<&Q>) -> bool
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+    {
+        let node = self.upsert_node(key, value);
+        self.move_node_after(node, anchor)
+    }
+
+    /// Inserts like [push_back](LinkedHashMap::push_back), then moves the entry right before
+    /// `anchor`. Returns false, leaving it where the insert put it, if `anchor` is missing or
+    /// is `key` itself.
+    pub(crate) fn push_before<Q>(&mut self, key: K, value: V, anchor: &Q) -> bool
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+    {
+        let node = self.upsert_node(key, value);
+        let anchor = match self.hash_map.get(Qey::from_ref(anchor)) {
+            Some(&anchor) if anchor != node => anchor,
+            _ => return false,
+        };
+        self.remove_node(node);
+        unsafe {
+            (*node).next = Some(anchor);
+            (*node).prev = (*anchor).prev;
+            match (*anchor).prev {
+                Some(prev) => (*prev).next = Some(node),
+                None => self.head = Some(node),
+            }
+            (*anchor).prev = Some(node);
+        }
+        true
+    }
+
+    fn upsert_node(&mut self, key: K, value: V) -> *mut Node<K, V> {
+        match self.hash_map.get(&KeyPtr { k: &key }) {
+            Some(&node) => {
+                unsafe { (*node).value = value };
+                node
+            }
+            None => {
+                self.push_back(key, value);
+                self.tail.expect("a key was just pushed")
+            }
+        }
+    }
+
+    fn move_node_after<Q>(&mut self, node: *mut Node<K, V>, anchor: Option<&Q>) -> bool
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+    {
+        let anchor = match anchor {
+            Some(anchor) => match self.hash_map.get(Qey::from_ref(anchor)) {
+                Some(&anchor) if anchor != node => anchor,
+                _ => return false,
+            },
+            None => {
+                self.remove_node(node);
+                unsafe { self.push_front_node(node) };
+                return true;
+            }
+        };
+        self.remove_node(node);
+        unsafe {
+            (*node).prev = Some(anchor);
+            (*node).next = (*anchor).next;
+            match (*anchor).next {
+                Some(next) => (*next).prev = Some(node),
+                None => self.tail = Some(node),
+            }
+            (*anchor).next = Some(node);
+        }
+        true
+    }
+
     #[inline]
-    pub fn take<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    pub fn take<Q>(&mut self, key: &Q) -> Option<(K, V)>
         where
             K: Borrow<Q>,
-            Q: Hash + Eq,
+            Q: Hash + Eq + ?Sized,
     {
         self.remove(key)
     }
@@ -367,10 +541,10 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     }
 
     #[inline]
-    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool
+    pub fn contains<Q>(&self, key: &Q) -> bool
         where
             K: Borrow<Q>,
-            Q: Hash + Eq,
+            Q: Hash + Eq + ?Sized,
     {
         self.hash_map.contains_key(Qey::from_ref(key))
     }
@@ -384,7 +558,7 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     pub fn position(&self, pos: usize) -> Option<(&K, &V)> {
         let mut next = self.head;
         for _ in 0..pos {
-            next = next.map(|node| unsafe { (*node).next }).flatten();
+            next = next.and_then(|node| unsafe { (*node).next });
         }
         next.map(|ptr| unsafe { (&(*ptr).key, &(*ptr).value) })
     }
@@ -393,7 +567,7 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
     pub fn position_mut(&mut self, pos: usize) -> Option<(&mut K, &mut V)> {
         let mut next = self.head;
         for _ in 0..pos {
-            next = next.map(|node| unsafe { (*node).next }).flatten();
+            next = next.and_then(|node| unsafe { (*node).next });
         }
         next.map(|ptr| unsafe { (&mut (*ptr).key, &mut (*ptr).value) })
     }
@@ -403,14 +577,42 @@ impl<K, V> LinkedHashMap<K, V, std::collections::hash_map::RandomState>
         while self.pop_back().is_some() {}
     }
 
+    /// Removes the first entry, from front to back, for which `f` returns true.
+    pub(crate) fn remove_first<F>(&mut self, mut f: F) -> Option<(K, V)>
+        where
+            F: FnMut(&K, &V) -> bool,
+    {
+        let mut next = self.head;
+        while let Some(node) = next {
+            unsafe {
+                if f(&(*node).key, &(*node).value) {
+                    self.hash_map.remove(&KeyPtr { k: &(*node).key });
+                    self.remove_node(node);
+                    let node = Box::from_raw(node);
+                    return Some((node.key, node.value));
+                }
+                next = (*node).next;
+            }
+        }
+        None
+    }
+
     #[inline]
-    pub fn iter(&self) -> Iter<K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
             head: self.head.map(|ptr| ptr as *const _),
             marker: PhantomData,
         }
     }
 
+    #[inline]
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            head: self.head,
+            marker: PhantomData,
+        }
+    }
+
     #[inline]
     pub fn _into_iter(mut self) -> IntoIter<K, V> {
         let head = self.head;
@@ -455,6 +657,11 @@ pub struct Iter<'a, K: 'a, V: 'a> {
     marker: PhantomData<(&'a K, &'a V)>,
 }
 
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    head: Option<*mut Node<K, V>>,
+    marker: PhantomData<(&'a K, &'a mut V)>,
+}
+
 pub struct IntoIter<K, V> {
     head: Option<*mut Node<K, V>>,
     marker: PhantomData<(K, V)>,
@@ -472,6 +679,17 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
     }
 }
 
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.head.map(|node| unsafe {
+            self.head = (*node).next;
+            (&(*node).key, &mut (*node).value)
+        })
+    }
+}
+
 impl<K, V> Iterator for IntoIter<K, V> {
     type Item = (K, V);
 
@@ -568,14 +786,16 @@ impl<K, V> Hash for LinkedHashMap<K, V>
     }
 }
 
-unsafe impl<K, V> Sync for LinkedHashMap<K, V>
+unsafe impl<K, V, S> Sync for LinkedHashMap<K, V, S>
     where
         K: Sync,
         V: Sync,
+        S: Sync,
 {}
 
-unsafe impl<K, V> Send for LinkedHashMap<K, V>
+unsafe impl<K, V, S> Send for LinkedHashMap<K, V, S>
     where
         K: Send,
         V: Send,
+        S: Send,
 {}
@@ -0,0 +1,364 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{CacheStats, LruCache, MemoryStore};
+
+/// Loads the value for a key missing from a [LoadingCache].
+///
+/// Closures `FnMut(&K) -> Result<V, E>` are loaders.
+pub trait Loader<K, V> {
+    type Error;
+
+    fn load(&mut self, key: &K) -> Result<V, Self::Error>;
+
+    /// Loads several keys at once, returning their values in the same order.
+    ///
+    /// The default implementation calls [load](Loader::load) for each key; override it when the
+    /// source can answer a batch more cheaply.
+    ///
+    /// # Panics
+    ///
+    /// [LoadingCache::get_all] panics if an override returns a different number of values than
+    /// it was given keys.
+    fn load_all(&mut self, keys: &[&K]) -> Result<Vec<V>, Self::Error> {
+        keys.iter().map(|key| self.load(key)).collect()
+    }
+}
+
+impl<K, V, E, F> Loader<K, V> for F
+where
+    F: FnMut(&K) -> Result<V, E>,
+{
+    type Error = E;
+
+    fn load(&mut self, key: &K) -> Result<V, E> {
+        self(key)
+    }
+}
+
+/// Where a [LoadingCache] writes values through to.
+pub trait Store<K, V> {
+    type Error;
+
+    fn write(&mut self, key: &K, value: &V) -> Result<(), Self::Error>;
+
+    fn delete(&mut self, key: &K) -> Result<(), Self::Error>;
+}
+
+impl<K, V, W> Store<K, V> for &mut W
+where
+    W: Store<K, V> + ?Sized,
+{
+    type Error = W::Error;
+
+    fn write(&mut self, key: &K, value: &V) -> Result<(), Self::Error> {
+        (**self).write(key, value)
+    }
+
+    fn delete(&mut self, key: &K) -> Result<(), Self::Error> {
+        (**self).delete(key)
+    }
+}
+
+impl<K, V> Store<K, V> for MemoryStore<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    type Error = Infallible;
+
+    fn write(&mut self, key: &K, value: &V) -> Result<(), Self::Error> {
+        self.map.insert(key.clone(), value.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &K) -> Result<(), Self::Error> {
+        self.map.remove(key);
+        Ok(())
+    }
+}
+
+/// The [Store] of a [LoadingCache] without write-through: writes only touch the cache.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoStore;
+
+impl<K, V> Store<K, V> for NoStore {
+    type Error = Infallible;
+
+    fn write(&mut self, _: &K, _: &V) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn delete(&mut self, _: &K) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Set while negative caching is on, to hand out copies of cached errors.
+type CloneFn<E> = fn(&E) -> E;
+
+enum Loaded<V, E> {
+    Value(V),
+    Error(E),
+}
+
+/// An LRU cache that fills itself from a [Loader] on misses.
+///
+/// [get](LoadingCache::get) handles the whole miss path: load, insert, and evict the least
+/// recently used entry if the cache is full. Failed loads are returned to the caller and not
+/// cached, unless [negative caching](LoadingCache::set_negative_caching) is enabled. With a
+/// [Store], [insert](LoadingCache::insert) and [remove](LoadingCache::remove) write through to
+/// it before touching the cache.
+///
+/// ```rust
+/// use linked_hash_map_rs::LoadingCache;
+///
+/// let mut cache = LoadingCache::new(100, |key: &u32| Ok::<_, String>(key * 2));
+/// assert_eq!(cache.get(&21), Ok(&42));
+/// assert_eq!(cache.stats().misses, 1);
+/// ```
+pub struct LoadingCache<K, V, L, W = NoStore, S = RandomState>
+where
+    L: Loader<K, V>,
+{
+    cache: LruCache<K, Loaded<V, L::Error>, S>,
+    loader: L,
+    store: W,
+    negative: Option<CloneFn<L::Error>>,
+    stats: CacheStats,
+}
+
+impl<K, V, L> LoadingCache<K, V, L>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+{
+    pub fn new(capacity: usize, loader: L) -> Self {
+        LoadingCache::with_store_and_hasher(capacity, loader, NoStore, RandomState::new())
+    }
+}
+
+impl<K, V, L, W> LoadingCache<K, V, L, W>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+    W: Store<K, V>,
+{
+    pub fn with_store(capacity: usize, loader: L, store: W) -> Self {
+        LoadingCache::with_store_and_hasher(capacity, loader, store, RandomState::new())
+    }
+}
+
+impl<K, V, L, W, S> LoadingCache<K, V, L, W, S>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+    W: Store<K, V>,
+    S: BuildHasher,
+{
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero: a loaded value must stay cached to be returned.
+    pub fn with_store_and_hasher(capacity: usize, loader: L, store: W, hasher: S) -> Self {
+        assert!(
+            capacity > 0,
+            "LoadingCache needs a capacity of at least one"
+        );
+        LoadingCache {
+            cache: LruCache::with_hasher(capacity, hasher),
+            loader,
+            store,
+            negative: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Caches failed loads too, so a key whose load failed keeps returning that error until it
+    /// is evicted or invalidated instead of being loaded again.
+    pub fn set_negative_caching(&mut self, enabled: bool)
+    where
+        L::Error: Clone,
+    {
+        self.negative = if enabled { Some(L::Error::clone) } else { None };
+    }
+
+    #[inline]
+    pub fn loader(&self) -> &L {
+        &self.loader
+    }
+
+    #[inline]
+    pub fn store(&self) -> &W {
+        &self.store
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(
+            capacity > 0,
+            "LoadingCache needs a capacity of at least one"
+        );
+        let len = self.cache.len();
+        self.cache.set_capacity(capacity);
+        self.stats.evictions += (len - self.cache.len()) as u64;
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    #[inline]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Returns whether `key` is cached, counting negatively cached errors.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.contains(key)
+    }
+
+    /// Returns the cached value for `key` without loading it or touching the recency order.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.cache.peek(key) {
+            Some(Loaded::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn put(&mut self, key: K, loaded: Loaded<V, L::Error>) {
+        if self.cache.insert(key, loaded).is_some() {
+            self.stats.evictions += 1;
+        }
+    }
+
+    /// Returns the value for `key`, loading it on a miss.
+    pub fn get(&mut self, key: &K) -> Result<&V, L::Error>
+    where
+        K: Clone,
+    {
+        if self.cache.contains(key) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            match self.loader.load(key) {
+                Ok(value) => self.put(key.clone(), Loaded::Value(value)),
+                Err(err) => {
+                    if let Some(clone) = self.negative {
+                        self.put(key.clone(), Loaded::Error(clone(&err)));
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        match self.cache.get(key) {
+            Some(Loaded::Value(value)) => Ok(value),
+            Some(Loaded::Error(err)) => Err(self.negative.expect("negative caching enabled")(err)),
+            None => unreachable!("a loaded entry is never evicted by its own insert"),
+        }
+    }
+
+    /// Returns clones of the values for `keys`, loading all misses with one
+    /// [load_all](Loader::load_all) call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `load_all` returns a different number of values than it was given keys. Nothing
+    /// it returned is cached in that case.
+    pub fn get_all(&mut self, keys: &[K]) -> Result<Vec<V>, L::Error>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut values = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            match self.cache.get(key) {
+                Some(Loaded::Value(value)) => {
+                    self.stats.hits += 1;
+                    values.push(Some(value.clone()));
+                }
+                Some(Loaded::Error(err)) => {
+                    self.stats.hits += 1;
+                    return Err(self.negative.expect("negative caching enabled")(err));
+                }
+                None => {
+                    self.stats.misses += 1;
+                    values.push(None);
+                    missing.push(i);
+                }
+            }
+        }
+        if !missing.is_empty() {
+            let missing_keys = missing.iter().map(|&i| &keys[i]).collect::<Vec<_>>();
+            let loaded = self.loader.load_all(&missing_keys)?;
+            assert_eq!(
+                loaded.len(),
+                missing.len(),
+                "load_all must return one value per key"
+            );
+            for (i, value) in missing.into_iter().zip(loaded) {
+                self.put(keys[i].clone(), Loaded::Value(value.clone()));
+                values[i] = Some(value);
+            }
+        }
+        Ok(values
+            .into_iter()
+            .map(|value| value.expect("every miss was loaded"))
+            .collect())
+    }
+
+    /// Writes `value` through to the store, then caches it as the most recently used entry.
+    ///
+    /// If the store fails the cache is left untouched.
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), W::Error> {
+        self.store.write(&key, &value)?;
+        self.put(key, Loaded::Value(value));
+        Ok(())
+    }
+
+    /// Deletes `key` from the store, then from the cache, returning the cached value if any.
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, W::Error> {
+        self.store.delete(key)?;
+        Ok(self.invalidate(key))
+    }
+
+    /// Drops `key` from the cache only, so the next [get](LoadingCache::get) loads it again.
+    pub fn invalidate<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.cache.remove(key) {
+            Some((_, Loaded::Value(value))) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Drops every cached entry, leaving the store as it is.
+    pub fn invalidate_all(&mut self) {
+        self.cache.clear()
+    }
+}
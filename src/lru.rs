@@ -0,0 +1,336 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{BuildHasher, Hash};
+
+use crate::LinkedHashMap;
+
+/// A capacity-bounded cache that evicts the least recently used entry.
+///
+/// Entries live in a [LinkedHashMap] ordered from least to most recently used:
+/// a hit moves the entry to the back and eviction pops from the front.
+///
+/// Pinned entries keep their place in that order and still count toward the capacity, but
+/// eviction skips over them.
+///
+/// ```rust
+/// use linked_hash_map_rs::LruCache;
+///
+/// let mut cache = LruCache::new(2);
+/// cache.insert(1, "a");
+/// cache.insert(2, "b");
+/// cache.get(&1);
+/// assert_eq!(cache.insert(3, "c"), Some((2, "b")));
+///
+/// cache.pin(&1);
+/// assert_eq!(cache.insert(4, "d"), Some((3, "c")));
+/// ```
+pub struct LruCache<K, V, S = RandomState> {
+    map: LinkedHashMap<K, Entry<V>, S>,
+    capacity: usize,
+    pinned: usize,
+    listener: Option<EvictionListener<K, V>>,
+}
+
+pub(crate) type EvictionListener<K, V> = Box<dyn FnMut(&K, &V) + Send + Sync>;
+
+struct Entry<V> {
+    value: V,
+    pinned: bool,
+}
+
+/// Returned by [LruCache::try_insert] when the cache is full and every entry is pinned.
+///
+/// Carries the entry that could not be inserted.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CacheFullError<K, V> {
+    pub key: K,
+    pub value: V,
+}
+
+impl<K, V> Display for CacheFullError<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cache is full and every entry is pinned")
+    }
+}
+
+impl<K: Debug, V: Debug> Error for CacheFullError<K, V> {}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new(capacity: usize) -> Self {
+        LruCache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> LruCache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        LruCache {
+            map: LinkedHashMap::with_hasher(hasher),
+            capacity,
+            pinned: 0,
+            listener: None,
+        }
+    }
+
+    /// Calls `listener` with every entry evicted to make room, by an insert or by shrinking
+    /// the capacity. Entries removed explicitly are not reported.
+    pub fn set_eviction_listener<F>(&mut self, listener: F)
+    where
+        F: FnMut(&K, &V) + Send + Sync + 'static,
+    {
+        self.listener = Some(Box::new(listener));
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, evicting least recently used entries until the cache fits.
+    ///
+    /// Pinned entries are never evicted, so the cache may stay over a reduced capacity until
+    /// enough of them are unpinned or removed. Growing does not allocate.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.map.len() > self.capacity && self.evict().is_some() {}
+    }
+
+    /// Releases memory the underlying map no longer needs, e.g. after shrinking the capacity.
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    #[inline]
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains(key)
+    }
+
+    /// Returns the value for `key` and marks it as most recently used.
+    #[inline]
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.move_to_back(key).map(|(_, e)| &e.value)
+    }
+
+    /// Returns the value for `key` and marks it as most recently used.
+    #[inline]
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.move_to_back(key)?;
+        self.map.get_mut(key).map(|e| &mut e.value)
+    }
+
+    /// Returns the value for `key` without touching the recency order.
+    #[inline]
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key).map(|e| &e.value)
+    }
+
+    /// Returns the stored key and value for `key` without touching the recency order.
+    #[inline]
+    pub fn peek_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get_key_value(key).map(|(k, e)| (k, &e.value))
+    }
+
+    /// Inserts `value` as the most recently used entry.
+    ///
+    /// Returns the entry evicted to make room for it, if any. When nothing can be evicted,
+    /// because the capacity is zero or every entry is pinned, the new entry is handed straight
+    /// back instead; use [try_insert](LruCache::try_insert) to tell the two cases apart.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.try_insert(key, value)
+            .unwrap_or_else(|err| Some((err.key, err.value)))
+    }
+
+    /// Inserts `value` as the most recently used entry, returning the evicted entry, if any.
+    ///
+    /// Fails if the cache is full and has no unpinned entry to evict.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<(K, V)>, CacheFullError<K, V>> {
+        if let Some(entry) = self.map.get_mut(&key) {
+            entry.value = value;
+            self.map.move_to_back(&key);
+            return Ok(None);
+        }
+        let evicted = if self.map.len() >= self.capacity {
+            match self.evict() {
+                Some(evicted) => Some(evicted),
+                None => return Err(CacheFullError { key, value }),
+            }
+        } else {
+            None
+        };
+        self.map.push_back(
+            key,
+            Entry {
+                value,
+                pinned: false,
+            },
+        );
+        Ok(evicted)
+    }
+
+    /// Appends an entry as the most recently used without evicting anything, for rebuilding a
+    /// cache whose pinned entries may keep it over capacity.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, key: K, value: V, pinned: bool) {
+        self.remove(&key);
+        if pinned {
+            self.pinned += 1;
+        }
+        self.map.push_back(key, Entry { value, pinned });
+    }
+
+    /// Removes `key` whether or not it is pinned.
+    #[inline]
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove(key).map(|(k, e)| {
+            if e.pinned {
+                self.pinned -= 1;
+            }
+            (k, e.value)
+        })
+    }
+
+    /// Removes and returns the least recently used entry that is not pinned.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let popped = if self.pinned == 0 {
+            self.map.pop_front()
+        } else if self.pinned == self.map.len() {
+            None
+        } else {
+            self.map.remove_first(|_, e| !e.pinned)
+        };
+        popped.map(|(k, e)| (k, e.value))
+    }
+
+    /// Removes the least recently used entry for which `f` returns true, pinned or not.
+    pub(crate) fn remove_first<F>(&mut self, mut f: F) -> Option<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let (k, e) = self.map.remove_first(|k, e| f(k, &e.value))?;
+        if e.pinned {
+            self.pinned -= 1;
+        }
+        Some((k, e.value))
+    }
+
+    fn evict(&mut self) -> Option<(K, V)> {
+        let evicted = self.pop_lru()?;
+        if let Some(listener) = &mut self.listener {
+            listener(&evicted.0, &evicted.1);
+        }
+        Some(evicted)
+    }
+
+    /// Returns the entry [pop_lru](LruCache::pop_lru) would remove, without touching the
+    /// recency order.
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        self.map
+            .iter()
+            .find(|(_, e)| !e.pinned)
+            .map(|(k, e)| (k, &e.value))
+    }
+
+    /// Protects `key` from eviction. Returns whether the key is cached.
+    pub fn pin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.map.get_mut(key) {
+            Some(entry) => {
+                if !entry.pinned {
+                    entry.pinned = true;
+                    self.pinned += 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Makes `key` evictable again. Returns whether the key is cached.
+    pub fn unpin<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.map.get_mut(key) {
+            Some(entry) => {
+                if entry.pinned {
+                    entry.pinned = false;
+                    self.pinned -= 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_pinned<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key).is_some_and(|e| e.pinned)
+    }
+
+    /// Number of pinned entries.
+    #[inline]
+    pub fn pinned_len(&self) -> usize {
+        self.pinned
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.pinned = 0;
+    }
+
+    /// Iterates from the least to the most recently used entry, pinned entries included.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.map.iter().map(|(k, e)| (k, &e.value))
+    }
+}
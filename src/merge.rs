@@ -0,0 +1,331 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+
+use crate::diff::longest_increasing;
+use crate::LinkedHashMap;
+
+/// Where [merge_with](LinkedHashMap::merge_with) puts keys that only the other map has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MergeOrder {
+    /// At the back, in the other map's order.
+    #[default]
+    AppendNew,
+    /// Next to the shared keys they sit next to in the other map: a run of new keys goes right
+    /// before the shared key that follows it there, or right after the last shared key if none
+    /// does. Without shared keys they go to the back.
+    Interleave,
+}
+
+type CombineFn<'a, K, V> = Box<dyn FnMut(&K, &mut V, V) + 'a>;
+
+enum Conflict<'a, K, V> {
+    KeepOurs,
+    TakeTheirs,
+    Combine(CombineFn<'a, K, V>),
+}
+
+/// How [merge_with](LinkedHashMap::merge_with) resolves keys present in both maps, and where
+/// it puts new ones.
+///
+/// Shared keys always keep their position in `self`.
+pub struct MergeStrategy<'a, K, V> {
+    conflict: Conflict<'a, K, V>,
+    order: MergeOrder,
+}
+
+impl<'a, K, V> MergeStrategy<'a, K, V> {
+    /// Shared keys keep the value in `self`.
+    pub fn keep_ours() -> Self {
+        MergeStrategy {
+            conflict: Conflict::KeepOurs,
+            order: MergeOrder::default(),
+        }
+    }
+
+    /// Shared keys take the value in the other map.
+    pub fn take_theirs() -> Self {
+        MergeStrategy {
+            conflict: Conflict::TakeTheirs,
+            order: MergeOrder::default(),
+        }
+    }
+
+    /// Shared keys are resolved by `combine(key, ours, theirs)`, which updates `ours` in place.
+    pub fn combine<F>(combine: F) -> Self
+    where
+        F: FnMut(&K, &mut V, V) + 'a,
+    {
+        MergeStrategy {
+            conflict: Conflict::Combine(Box::new(combine)),
+            order: MergeOrder::default(),
+        }
+    }
+
+    pub fn order(mut self, order: MergeOrder) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+impl<K, V, S> LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Merges every entry of `other` into `self`, resolving shared keys and placing new ones as
+    /// `strategy` says.
+    ///
+    /// ```rust
+    /// use linked_hash_map_rs::{LinkedHashMap, MergeOrder, MergeStrategy};
+    ///
+    /// let mut config = LinkedHashMap::new();
+    /// config.extend(vec![("host", "localhost"), ("port", "80")]);
+    /// let mut user = LinkedHashMap::new();
+    /// user.extend(vec![("scheme", "https"), ("host", "example.com"), ("port", "443")]);
+    ///
+    /// config.merge_with(user, MergeStrategy::take_theirs().order(MergeOrder::Interleave));
+    /// assert_eq!(
+    ///     config.iter().collect::<Vec<_>>(),
+    ///     vec![(&"scheme", &"https"), (&"host", &"example.com"), (&"port", &"443")]
+    /// );
+    /// ```
+    pub fn merge_with<T>(
+        &mut self,
+        mut other: LinkedHashMap<K, V, T>,
+        strategy: MergeStrategy<K, V>,
+    ) where
+        T: BuildHasher,
+    {
+        let MergeStrategy {
+            mut conflict,
+            order,
+        } = strategy;
+        // New keys seen since the last shared key, waiting for the next one to anchor them.
+        let mut run = Vec::new();
+        let mut last_shared = None;
+        while let Some((key, value)) = other.pop_front() {
+            let ours = match self.get_mut(&key) {
+                Some(ours) => ours,
+                None if order == MergeOrder::AppendNew => {
+                    self.push_back(key, value);
+                    continue;
+                }
+                None => {
+                    run.push((key, value));
+                    continue;
+                }
+            };
+            match &mut conflict {
+                Conflict::KeepOurs => {}
+                Conflict::TakeTheirs => *ours = value,
+                Conflict::Combine(combine) => combine(&key, ours, value),
+            }
+            for (new_key, new_value) in run.drain(..) {
+                self.push_before(new_key, new_value, &key);
+            }
+            last_shared = Some(key);
+        }
+        match last_shared {
+            Some(anchor) => {
+                for (new_key, new_value) in run.into_iter().rev() {
+                    self.push_after(new_key, new_value, Some(&anchor));
+                }
+            }
+            None => {
+                for (new_key, new_value) in run {
+                    self.push_back(new_key, new_value);
+                }
+            }
+        }
+    }
+}
+
+/// Entries [merge3](LinkedHashMap::merge3) could not reconcile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeConflict<K, V> {
+    /// Both sides changed the value differently, or one changed it and the other removed it.
+    /// `None` means the key is absent on that side.
+    Value {
+        key: K,
+        base: Option<V>,
+        ours: Option<V>,
+        theirs: Option<V>,
+    },
+    /// Both sides moved the key, to follow different keys. `None` means the front.
+    Order {
+        key: K,
+        ours_after: Option<K>,
+        theirs_after: Option<K>,
+    },
+}
+
+/// The result of [merge3](LinkedHashMap::merge3).
+pub struct ThreeWayMerge<K, V, S = RandomState> {
+    /// The merged map, holding our side of every conflict.
+    pub merged: LinkedHashMap<K, V, S>,
+    pub conflicts: Vec<MergeConflict<K, V>>,
+}
+
+impl<K, V, S> ThreeWayMerge<K, V, S> {
+    #[inline]
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl<K, V, S> LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Merges the changes `ours` and `theirs` each made to `base`, values and order alike.
+    ///
+    /// A value changed on one side only takes that change; the same change on both sides is
+    /// taken once. The merged order starts from ours, then applies the keys theirs added or
+    /// moved, each placed after the key it follows in theirs. Keys on a longest common
+    /// subsequence with `base` count as not moved, as in [diff](LinkedHashMap::diff).
+    ///
+    /// ```rust
+    /// use linked_hash_map_rs::{LinkedHashMap, MergeConflict};
+    ///
+    /// let mut base = LinkedHashMap::new();
+    /// base.extend(vec![("title", 1), ("body", 2), ("tags", 3)]);
+    /// let mut ours = base.clone();
+    /// ours.insert("body", 20);
+    /// ours.insert("title", 10);
+    /// let mut theirs = base.clone();
+    /// theirs.move_to_front(&"tags");
+    /// theirs.insert("title", 11);
+    ///
+    /// let merge = LinkedHashMap::merge3(&base, &ours, &theirs);
+    /// assert_eq!(
+    ///     merge.merged.iter().collect::<Vec<_>>(),
+    ///     vec![(&"tags", &3), (&"title", &10), (&"body", &20)]
+    /// );
+    /// assert_eq!(
+    ///     merge.conflicts,
+    ///     vec![MergeConflict::Value {
+    ///         key: "title",
+    ///         base: Some(1),
+    ///         ours: Some(10),
+    ///         theirs: Some(11),
+    ///     }]
+    /// );
+    /// ```
+    pub fn merge3(base: &Self, ours: &Self, theirs: &Self) -> ThreeWayMerge<K, V, S>
+    where
+        K: Clone,
+        V: Clone + PartialEq,
+        S: Default,
+    {
+        let mut conflicts = Vec::new();
+        let mut resolved = HashMap::new();
+        for (key, _) in ours.iter().chain(theirs.iter()).chain(base.iter()) {
+            if resolved.contains_key(key) {
+                continue;
+            }
+            let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+            let value = if o == t || t == b {
+                o
+            } else if o == b {
+                t
+            } else {
+                conflicts.push(MergeConflict::Value {
+                    key: key.clone(),
+                    base: b.cloned(),
+                    ours: o.cloned(),
+                    theirs: t.cloned(),
+                });
+                o
+            };
+            resolved.insert(key, value);
+        }
+        let kept = |key: &K| resolved.get(key).is_some_and(Option::is_some);
+
+        let mut merged = LinkedHashMap::with_hasher(S::default());
+        for (key, _) in ours.iter() {
+            if let Some(value) = resolved[key] {
+                merged.push_back(key.clone(), value.clone());
+            }
+        }
+
+        let ours_moved = moved(base, ours);
+        let theirs_moved = moved(base, theirs);
+        let ours_after = anchors(ours, kept);
+        let mut after = None;
+        for (key, _) in theirs.iter() {
+            let value = match resolved[key] {
+                Some(value) => value,
+                None => continue,
+            };
+            if !base.contains(key) {
+                if !ours.contains(key) {
+                    merged.push_after(key.clone(), value.clone(), after);
+                }
+            } else if theirs_moved.contains(key) {
+                match ours_after.get(key) {
+                    Some(&ours_after) if ours_moved.contains(key) && ours_after != after => {
+                        conflicts.push(MergeConflict::Order {
+                            key: key.clone(),
+                            ours_after: ours_after.cloned(),
+                            theirs_after: after.cloned(),
+                        })
+                    }
+                    _ => {
+                        merged.move_after(key, after);
+                    }
+                }
+            }
+            after = Some(key);
+        }
+        ThreeWayMerge { merged, conflicts }
+    }
+}
+
+/// Keys of `side` that moved relative to `base`: those off a longest common subsequence of the
+/// two key orders.
+fn moved<'a, K, V, S>(
+    base: &LinkedHashMap<K, V, S>,
+    side: &'a LinkedHashMap<K, V, S>,
+) -> HashSet<&'a K>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    let positions = base
+        .iter()
+        .enumerate()
+        .map(|(i, (k, _))| (k, i))
+        .collect::<HashMap<_, _>>();
+    let shared = side
+        .iter()
+        .filter_map(|(k, _)| positions.get(k).map(|&i| (k, i)))
+        .collect::<Vec<_>>();
+    let stable = longest_increasing(&shared.iter().map(|&(_, i)| i).collect::<Vec<_>>());
+    shared
+        .into_iter()
+        .zip(stable)
+        .filter(|&(_, stable)| !stable)
+        .map(|((k, _), _)| k)
+        .collect()
+}
+
+/// Maps each key of `side` to the closest key before it that passes `kept`, or `None` for the
+/// front.
+fn anchors<K, V, S, F>(side: &LinkedHashMap<K, V, S>, kept: F) -> HashMap<&K, Option<&K>>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    F: Fn(&K) -> bool,
+{
+    let mut after = None;
+    let mut anchors = HashMap::new();
+    for (key, _) in side.iter() {
+        anchors.insert(key, after);
+        if kept(key) {
+            after = Some(key);
+        }
+    }
+    anchors
+}
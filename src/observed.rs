@@ -0,0 +1,350 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use crate::{Iter, LinkedHashMap};
+
+/// An end of a [LinkedHashMap].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum End {
+    Front,
+    Back,
+}
+
+/// A change made to an [ObservedLinkedHashMap], in enough detail to
+/// [apply](LinkedHashMap::apply) it to a replica.
+///
+/// Observers get events borrowing from the map; [cloned](MapEvent::cloned) turns one into an
+/// owned event to send elsewhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapEvent<K, V> {
+    /// A new key was pushed to one end.
+    Inserted {
+        key: K,
+        value: V,
+        at: End,
+    },
+    /// An existing key took a new value, keeping its position.
+    Updated {
+        key: K,
+        old: V,
+        new: V,
+    },
+    /// A key was removed, explicitly or by popping an end.
+    Removed {
+        key: K,
+        value: V,
+    },
+    /// A key moved to one end. `from` and `to` are positions counted from the front.
+    Moved {
+        key: K,
+        from: usize,
+        to: usize,
+    },
+    Cleared,
+}
+
+impl<K: Clone, V: Clone> MapEvent<&K, &V> {
+    pub fn cloned(&self) -> MapEvent<K, V> {
+        match *self {
+            MapEvent::Inserted { key, value, at } => MapEvent::Inserted {
+                key: key.clone(),
+                value: value.clone(),
+                at,
+            },
+            MapEvent::Updated { key, old, new } => MapEvent::Updated {
+                key: key.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            },
+            MapEvent::Removed { key, value } => MapEvent::Removed {
+                key: key.clone(),
+                value: value.clone(),
+            },
+            MapEvent::Moved { key, from, to } => MapEvent::Moved {
+                key: key.clone(),
+                from,
+                to,
+            },
+            MapEvent::Cleared => MapEvent::Cleared,
+        }
+    }
+}
+
+impl<K, V, S> LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Replays `event` from an [ObservedLinkedHashMap], so a replica that starts equal to the
+    /// source and applies its events in order stays equal, order included.
+    pub fn apply(&mut self, event: MapEvent<K, V>) {
+        match event {
+            MapEvent::Inserted {
+                key,
+                value,
+                at: End::Front,
+            } => {
+                self.push_front(key, value);
+            }
+            MapEvent::Inserted {
+                key,
+                value,
+                at: End::Back,
+            } => {
+                self.push_back(key, value);
+            }
+            MapEvent::Updated { key, new, .. } => {
+                self.insert(key, new);
+            }
+            MapEvent::Removed { key, .. } => {
+                self.remove(&key);
+            }
+            MapEvent::Moved { key, to: 0, .. } => {
+                self.move_to_front(&key);
+            }
+            MapEvent::Moved { key, .. } => {
+                self.move_to_back(&key);
+            }
+            MapEvent::Cleared => self.clear(),
+        }
+    }
+}
+
+type Observer<K, V> = Box<dyn for<'a> FnMut(MapEvent<&'a K, &'a V>) + Send + Sync>;
+
+/// A [LinkedHashMap] that reports every mutation to an observer as a [MapEvent].
+///
+/// Events are emitted after the change is made. Reporting a move counts the key's position
+/// first, which is linear in the length of the map.
+///
+/// ```rust
+/// use linked_hash_map_rs::{LinkedHashMap, ObservedLinkedHashMap};
+/// use std::sync::mpsc::channel;
+///
+/// let (sender, receiver) = channel();
+/// let mut registry = ObservedLinkedHashMap::new();
+/// registry.set_observer(move |event| sender.send(event.cloned()).unwrap());
+/// registry.insert("a", 1);
+/// registry.insert("b", 2);
+/// registry.move_to_front(&"b");
+///
+/// let mut replica = LinkedHashMap::new();
+/// receiver.try_iter().for_each(|event| replica.apply(event));
+/// assert_eq!(replica.iter().collect::<Vec<_>>(), registry.iter().collect::<Vec<_>>());
+/// ```
+pub struct ObservedLinkedHashMap<K, V, S = RandomState> {
+    map: LinkedHashMap<K, V, S>,
+    observer: Option<Observer<K, V>>,
+}
+
+impl<K, V> ObservedLinkedHashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new() -> Self {
+        ObservedLinkedHashMap::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V> Default for ObservedLinkedHashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    fn default() -> Self {
+        ObservedLinkedHashMap::new()
+    }
+}
+
+impl<K, V, S> ObservedLinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn with_hasher(hasher: S) -> Self {
+        ObservedLinkedHashMap {
+            map: LinkedHashMap::with_hasher(hasher),
+            observer: None,
+        }
+    }
+
+    /// Calls `observer` with every later mutation, replacing any previous observer.
+    pub fn set_observer<F>(&mut self, observer: F)
+    where
+        F: for<'a> FnMut(MapEvent<&'a K, &'a V>) + Send + Sync + 'static,
+    {
+        self.observer = Some(Box::new(observer));
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains(key)
+    }
+
+    #[inline]
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.map.front()
+    }
+
+    #[inline]
+    pub fn back(&self) -> Option<(&K, &V)> {
+        self.map.back()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    /// Same as [push_back](ObservedLinkedHashMap::push_back).
+    #[inline]
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.push_back(key, value)
+    }
+
+    /// Pushes a new key to the front, or updates an existing one in place, returning the old
+    /// value.
+    pub fn push_front(&mut self, key: K, value: V) -> Option<V> {
+        self.push(key, value, End::Front)
+    }
+
+    /// Pushes a new key to the back, or updates an existing one in place, returning the old
+    /// value.
+    pub fn push_back(&mut self, key: K, value: V) -> Option<V> {
+        self.push(key, value, End::Back)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = self.map.remove(key);
+        self.emit_removed(removed)
+    }
+
+    pub fn pop_front(&mut self) -> Option<(K, V)> {
+        let popped = self.map.pop_front();
+        self.emit_removed(popped)
+    }
+
+    pub fn pop_back(&mut self) -> Option<(K, V)> {
+        let popped = self.map.pop_back();
+        self.emit_removed(popped)
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.move_to(key, End::Front)
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.move_to(key, End::Back)
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        if let Some(observer) = &mut self.observer {
+            observer(MapEvent::Cleared);
+        }
+    }
+
+    fn push(&mut self, key: K, value: V, at: End) -> Option<V> {
+        if let Some(slot) = self.map.get_mut(&key) {
+            let old = mem::replace(slot, value);
+            if let Some(observer) = &mut self.observer {
+                let (key, new) = self
+                    .map
+                    .get_key_value(&key)
+                    .expect("the key was just found");
+                observer(MapEvent::Updated {
+                    key,
+                    old: &old,
+                    new,
+                });
+            }
+            return Some(old);
+        }
+        let (key, value) = match at {
+            End::Front => {
+                self.map.push_front(key, value);
+                self.map.front()
+            }
+            End::Back => {
+                self.map.push_back(key, value);
+                self.map.back()
+            }
+        }
+        .expect("the key was just pushed");
+        if let Some(observer) = &mut self.observer {
+            observer(MapEvent::Inserted { key, value, at });
+        }
+        None
+    }
+
+    fn move_to<Q>(&mut self, key: &Q, end: End) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let observer = match &mut self.observer {
+            Some(observer) => observer,
+            None => {
+                return match end {
+                    End::Front => self.map.move_to_front(key).is_some(),
+                    End::Back => self.map.move_to_back(key).is_some(),
+                }
+            }
+        };
+        let from = match self.map.iter().position(|(k, _)| k.borrow() == key) {
+            Some(from) => from,
+            None => return false,
+        };
+        let last = self.map.len() - 1;
+        let (key, to) = match end {
+            End::Front => (self.map.move_to_front(key), 0),
+            End::Back => (self.map.move_to_back(key), last),
+        };
+        let (key, _) = key.expect("the key was just found");
+        observer(MapEvent::Moved { key, from, to });
+        true
+    }
+
+    fn emit_removed(&mut self, removed: Option<(K, V)>) -> Option<(K, V)> {
+        if let (Some((key, value)), Some(observer)) = (&removed, &mut self.observer) {
+            observer(MapEvent::Removed { key, value });
+        }
+        removed
+    }
+}
@@ -0,0 +1,291 @@
+//! Snapshots of maps and caches that keep their order and configuration across restarts.
+//!
+//! A snapshot is a short header (magic, format version, what kind of container wrote it)
+//! followed by the container's [Serialize] form from [crate::serde], encoded with bincode.
+//! Loading goes through the matching [Deserialize](serde::Deserialize) impl or seed, so a
+//! snapshot restores exactly what serde round trips do.
+
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+use bincode::Options;
+use serde::de::{DeserializeOwned, DeserializeSeed};
+use serde::Serialize;
+
+use crate::serde::{TtlCacheSeed, WriteBackCacheSeed};
+use crate::{
+    Clock, ConcurrentLru, Flusher, LinkedHashMap, LruCache, ShardedLru, SystemClock, TtlCache,
+    WriteBackCache,
+};
+
+const MAGIC: &[u8; 4] = b"LHMR";
+const VERSION: u8 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Map = 0,
+    Lru = 1,
+    Ttl = 2,
+    Sharded = 3,
+    Concurrent = 4,
+    WriteBack = 5,
+}
+
+impl Kind {
+    fn name(byte: u8) -> &'static str {
+        match byte {
+            0 => "LinkedHashMap",
+            1 => "LruCache",
+            2 => "TtlCache",
+            3 => "ShardedLru",
+            4 => "ConcurrentLru",
+            5 => "WriteBackCache",
+            _ => "unknown container",
+        }
+    }
+}
+
+fn save<W, T>(mut writer: W, kind: Kind, payload: &T) -> io::Result<()>
+where
+    W: Write,
+    T: Serialize,
+{
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, kind as u8])?;
+    bincode::serialize_into(writer, payload).map_err(|err| into_io(*err))
+}
+
+fn load<R, T, U>(mut reader: R, kind: Kind, seed: T) -> io::Result<U>
+where
+    R: Read,
+    T: for<'de> DeserializeSeed<'de, Value = U>,
+{
+    let mut header = [0; 6];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid_data(
+            "not a linked-hash-map-rs snapshot".to_string(),
+        ));
+    }
+    if header[4] != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported snapshot version {}",
+            header[4]
+        )));
+    }
+    if header[5] != kind as u8 {
+        return Err(invalid_data(format!(
+            "snapshot holds a {}, not a {}",
+            Kind::name(header[5]),
+            Kind::name(kind as u8)
+        )));
+    }
+    // The options `bincode::serialize_into` writes with.
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    seed.deserialize(&mut bincode::Deserializer::with_reader(reader, options))
+        .map_err(|err| into_io(*err))
+}
+
+fn into_io(err: bincode::ErrorKind) -> io::Error {
+    match err {
+        bincode::ErrorKind::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<K, V, S> LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Writes every entry, front to back.
+    pub fn save_to<W: Write>(&self, writer: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        save(writer, Kind::Map, self)
+    }
+
+    /// Reads a map written by [save_to](LinkedHashMap::save_to), in the same order.
+    pub fn load_from<R: Read>(reader: R) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        load(reader, Kind::Map, PhantomData)
+    }
+}
+
+impl<K, V, S> LruCache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Writes the capacity and every entry from least to most recently used, with its pin.
+    pub fn save_to<W: Write>(&self, writer: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        save(writer, Kind::Lru, self)
+    }
+
+    /// Reads a cache written by [save_to](LruCache::save_to), with the same capacity, recency
+    /// order and pins.
+    pub fn load_from<R: Read>(reader: R) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        load(reader, Kind::Lru, PhantomData)
+    }
+}
+
+impl<K, V, S> TtlCache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Reads a cache written by [save_to](TtlCache::save_to) that measures time with the
+    /// system clock.
+    pub fn load_from<R: Read>(reader: R) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        TtlCache::load_from_with_clock(reader, SystemClock)
+    }
+}
+
+impl<K, V, S, C> TtlCache<K, V, S, C>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    C: Clock,
+{
+    /// Writes the capacity, the TTL and every unexpired entry from least to most recently
+    /// used, with its age.
+    pub fn save_to<W: Write>(&self, writer: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        save(writer, Kind::Ttl, self)
+    }
+
+    /// Reads a cache written by [save_to](TtlCache::save_to), with the same capacity, TTL and
+    /// recency order. Entries keep the age they had when saved, so time spent on disk does not
+    /// count toward their expiry.
+    pub fn load_from_with_clock<R: Read>(reader: R, clock: C) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        load(
+            reader,
+            Kind::Ttl,
+            TtlCacheSeed::with_hasher_and_clock(S::default(), clock),
+        )
+    }
+}
+
+impl<K, V, S> ShardedLru<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    /// Writes the shard count, the per-shard capacity and every shard's entries from least to
+    /// most recently used, with their pins.
+    ///
+    /// Every shard stays locked until the snapshot is written, so it is consistent even while
+    /// the cache is in use.
+    pub fn save_to<W: Write>(&self, writer: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        save(writer, Kind::Sharded, self)
+    }
+
+    /// Reads a cache written by [save_to](ShardedLru::save_to) with the same shard count and
+    /// capacity. Keys are hashed to shards again, keeping their relative recency order.
+    pub fn load_from<R: Read>(reader: R) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        load(reader, Kind::Sharded, PhantomData)
+    }
+}
+
+impl<K, V, S> ConcurrentLru<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Applies the buffered reads, then writes the capacity and every entry from least to most
+    /// recently used, with its pin.
+    pub fn save_to<W: Write>(&self, writer: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        save(writer, Kind::Concurrent, self)
+    }
+
+    /// Reads a cache written by [save_to](ConcurrentLru::save_to), with the same capacity,
+    /// recency order and pins.
+    pub fn load_from<R: Read>(reader: R) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        load(reader, Kind::Concurrent, PhantomData)
+    }
+}
+
+impl<K, V, F, S> WriteBackCache<K, V, F, S>
+where
+    K: Hash + Eq,
+    F: Flusher<K, V>,
+    S: BuildHasher,
+{
+    /// Writes the capacity and every entry from least to most recently used, with its dirty
+    /// flag, so pending writes survive the restart.
+    pub fn save_to<W: Write>(&self, writer: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        save(writer, Kind::WriteBack, self)
+    }
+
+    /// Reads a cache written by [save_to](WriteBackCache::save_to) that flushes to `flusher`,
+    /// with the same capacity, recency order and dirty entries.
+    pub fn load_from<R: Read>(reader: R, flusher: F) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        S: Default,
+    {
+        load(
+            reader,
+            Kind::WriteBack,
+            WriteBackCacheSeed::with_hasher(flusher, S::default()),
+        )
+    }
+}
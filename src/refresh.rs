@@ -0,0 +1,331 @@
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::sharded::lock;
+use crate::{Clock, Loader, SystemClock, TtlCache};
+
+/// Runs the reloads a [RefreshingCache] schedules for stale entries.
+///
+/// Every task must run eventually: a key whose reload never finishes is not refreshed again
+/// until it expires.
+pub trait Executor {
+    fn execute(&self, task: Box<dyn FnOnce() + Send>);
+}
+
+/// Runs each reload right away, inside the `get` that scheduled it. The stale value is still
+/// returned; the reloaded one is picked up by the next call.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImmediateExecutor;
+
+impl Executor for ImmediateExecutor {
+    fn execute(&self, task: Box<dyn FnOnce() + Send>) {
+        task()
+    }
+}
+
+/// Queues reloads until [run_pending](DeferredExecutor::run_pending) is called, e.g. once per
+/// request outside the latency-critical path.
+#[derive(Default)]
+pub struct DeferredExecutor {
+    tasks: RefCell<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+impl DeferredExecutor {
+    pub fn new() -> Self {
+        DeferredExecutor::default()
+    }
+
+    /// Number of queued tasks.
+    pub fn pending(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    /// Runs every queued task, returning how many ran.
+    pub fn run_pending(&self) -> usize {
+        let tasks = self.tasks.take();
+        let ran = tasks.len();
+        tasks.into_iter().for_each(|task| task());
+        ran
+    }
+}
+
+impl Executor for DeferredExecutor {
+    fn execute(&self, task: Box<dyn FnOnce() + Send>) {
+        self.tasks.borrow_mut().push(task)
+    }
+}
+
+/// A finished reload: the key, the token it was scheduled with, and the value if it loaded.
+type Reload<K, V> = (K, u64, Option<V>);
+
+/// A [TtlCache] that fills itself from a [Loader] and reloads entries before they expire.
+///
+/// A miss or an expired entry is loaded synchronously by [get](RefreshingCache::get). Once
+/// [refresh_after](RefreshingCache::set_refresh_after) is set, a hit on an entry at least that
+/// old returns the cached value straight away and hands a reload to the [Executor]; the new
+/// value replaces the old one on a later call. Failed reloads are dropped, leaving the old
+/// value to expire normally, and so are reloads overtaken by an
+/// [insert](RefreshingCache::insert), an [invalidate](RefreshingCache::invalidate) or a
+/// synchronous load of the same key.
+///
+/// Any [Loader] a [LoadingCache](crate::LoadingCache) takes works here too, stateful ones
+/// included, because foreground loads and reloads share it behind a mutex. The price is that a
+/// miss waits for a reload of another key that is running at the same time.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use linked_hash_map_rs::{ManualClock, RefreshingCache};
+///
+/// let clock = ManualClock::new();
+/// let mut version = 0;
+/// let loader = move |_: &&str| {
+///     version += 1;
+///     Ok::<_, ()>(version)
+/// };
+/// let mut cache = RefreshingCache::with_clock(10, Duration::from_secs(60), loader, clock.clone());
+/// cache.set_refresh_after(Duration::from_secs(30));
+///
+/// assert_eq!(cache.get(&"config"), Ok(&1));
+/// clock.advance(Duration::from_secs(45));
+/// assert_eq!(cache.get(&"config"), Ok(&1));
+/// assert_eq!(cache.get(&"config"), Ok(&2));
+/// ```
+pub struct RefreshingCache<K, V, L, X = ImmediateExecutor, C = SystemClock, S = RandomState> {
+    cache: TtlCache<K, V, S, C>,
+    loader: Arc<Mutex<L>>,
+    executor: X,
+    refresh_after: Option<Duration>,
+    /// The token of the reload each key is waiting for.
+    refreshing: HashMap<K, u64, S>,
+    next_token: u64,
+    sender: Sender<Reload<K, V>>,
+    receiver: Receiver<Reload<K, V>>,
+}
+
+impl<K, V, L> RefreshingCache<K, V, L>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+{
+    pub fn new(capacity: usize, ttl: Duration, loader: L) -> Self {
+        RefreshingCache::with_executor_and_clock(
+            capacity,
+            ttl,
+            loader,
+            ImmediateExecutor,
+            SystemClock,
+        )
+    }
+}
+
+impl<K, V, L, C> RefreshingCache<K, V, L, ImmediateExecutor, C>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+    C: Clock,
+{
+    pub fn with_clock(capacity: usize, ttl: Duration, loader: L, clock: C) -> Self {
+        RefreshingCache::with_executor_and_clock(capacity, ttl, loader, ImmediateExecutor, clock)
+    }
+}
+
+impl<K, V, L, X> RefreshingCache<K, V, L, X>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+    X: Executor,
+{
+    pub fn with_executor(capacity: usize, ttl: Duration, loader: L, executor: X) -> Self {
+        RefreshingCache::with_executor_and_clock(capacity, ttl, loader, executor, SystemClock)
+    }
+}
+
+impl<K, V, L, X, C> RefreshingCache<K, V, L, X, C>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+    X: Executor,
+    C: Clock,
+{
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero: a loaded value must stay cached to be returned.
+    pub fn with_executor_and_clock(
+        capacity: usize,
+        ttl: Duration,
+        loader: L,
+        executor: X,
+        clock: C,
+    ) -> Self {
+        RefreshingCache::with_hasher(capacity, ttl, loader, executor, clock, RandomState::new())
+    }
+}
+
+impl<K, V, L, X, C, S> RefreshingCache<K, V, L, X, C, S>
+where
+    K: Hash + Eq,
+    L: Loader<K, V>,
+    X: Executor,
+    C: Clock,
+    S: BuildHasher,
+{
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero: a loaded value must stay cached to be returned.
+    pub fn with_hasher(
+        capacity: usize,
+        ttl: Duration,
+        loader: L,
+        executor: X,
+        clock: C,
+        hasher: S,
+    ) -> Self
+    where
+        S: Clone,
+    {
+        assert!(
+            capacity > 0,
+            "RefreshingCache needs a capacity of at least one"
+        );
+        let (sender, receiver) = channel();
+        RefreshingCache {
+            cache: TtlCache::with_hasher_and_clock(capacity, ttl, hasher.clone(), clock),
+            loader: Arc::new(Mutex::new(loader)),
+            executor,
+            refresh_after: None,
+            refreshing: HashMap::with_hasher(hasher),
+            next_token: 0,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Age after which a hit schedules a reload. Only useful below the TTL.
+    pub fn set_refresh_after(&mut self, after: Duration) {
+        self.refresh_after = Some(after);
+    }
+
+    #[inline]
+    pub fn refresh_after(&self) -> Option<Duration> {
+        self.refresh_after
+    }
+
+    #[inline]
+    pub fn ttl(&self) -> Duration {
+        self.cache.ttl()
+    }
+
+    #[inline]
+    pub fn executor(&self) -> &X {
+        &self.executor
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// Number of stored entries, including expired ones that have not been looked up yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Number of scheduled reloads whose result is still wanted.
+    #[inline]
+    pub fn pending_refreshes(&self) -> usize {
+        self.refreshing.len()
+    }
+
+    /// Caches `value` with a fresh expiry. A reload already scheduled for `key` is discarded
+    /// when it finishes.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.refreshing.remove(&key);
+        self.cache.insert(key, value);
+    }
+
+    /// Drops `key`, so the next [get](RefreshingCache::get) loads it again. A reload already
+    /// scheduled for it is discarded when it finishes.
+    pub fn invalidate(&mut self, key: &K) -> Option<V> {
+        self.refreshing.remove(key);
+        self.cache.remove(key).map(|(_, v)| v)
+    }
+
+    /// Drops every entry, discarding all scheduled reloads when they finish.
+    pub fn invalidate_all(&mut self) {
+        self.refreshing.clear();
+        self.cache.clear()
+    }
+}
+
+impl<K, V, L, X, C, S> RefreshingCache<K, V, L, X, C, S>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Send + 'static,
+    L: Loader<K, V> + Send + 'static,
+    X: Executor,
+    C: Clock,
+    S: BuildHasher,
+{
+    /// Returns the value for `key`, loading it if it is missing or expired and scheduling a
+    /// reload if it is older than [refresh_after](RefreshingCache::set_refresh_after).
+    pub fn get(&mut self, key: &K) -> Result<&V, L::Error> {
+        self.apply_reloads();
+        match self.cache.age(key) {
+            Some(age) if age < self.cache.ttl() => {
+                if self.refresh_after.is_some_and(|after| age >= after)
+                    && !self.refreshing.contains_key(key)
+                {
+                    self.schedule(key.clone());
+                }
+            }
+            _ => {
+                let value = lock(&self.loader).load(key)?;
+                self.insert(key.clone(), value);
+            }
+        }
+        Ok(self
+            .cache
+            .get(key)
+            .expect("a fresh entry is neither expired nor evicted by its own insert"))
+    }
+
+    /// Applies the results of finished reloads without waiting for the next
+    /// [get](RefreshingCache::get).
+    pub fn apply_reloads(&mut self) {
+        while let Ok((key, token, value)) = self.receiver.try_recv() {
+            if self.refreshing.get(&key) != Some(&token) {
+                continue;
+            }
+            self.refreshing.remove(&key);
+            if let Some(value) = value {
+                if self.cache.age(&key).is_some() {
+                    self.cache.insert(key, value);
+                }
+            }
+        }
+    }
+
+    fn schedule(&mut self, key: K) {
+        let token = self.next_token;
+        self.next_token += 1;
+        self.refreshing.insert(key.clone(), token);
+        let loader = Arc::clone(&self.loader);
+        let sender = self.sender.clone();
+        self.executor.execute(Box::new(move || {
+            let value = lock(&loader).load(&key).ok();
+            // The cache may be gone by now, in which case nobody wants the value.
+            let _ = sender.send((key, token, value));
+        }));
+    }
+}
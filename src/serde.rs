@@ -1,21 +1,36 @@
-use std::fmt::Formatter;
-use std::hash::Hash;
+//! [Serialize] and [Deserialize] for [LinkedHashMap], keeping the entry order, and for the
+//! [LruCache], [ConcurrentLru], [ShardedLru], [TtlCache] and [WriteBackCache] caches, keeping
+//! their recency order, pins and dirty flags.
+//!
+//! `TieredCache` has no implementations: its disk tier is scratch space tied to a segment file,
+//! so there is nothing a deserializer could rebuild it from without a path.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash};
 use std::marker::PhantomData;
+use std::mem;
+use std::time::Duration;
 
-use serde::de::{MapAccess, Visitor};
-use serde::ser::SerializeMap;
+use serde::de::{DeserializeSeed, Error, MapAccess, SeqAccess, Unexpected, Visitor};
+use serde::ser::{SerializeMap, SerializeStruct};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::LinkedHashMap;
+use crate::sharded::lock;
+use crate::{
+    Clock, ConcurrentLru, Flusher, LinkedHashMap, LruCache, ShardedLru, SystemClock, TtlCache,
+    WriteBackCache,
+};
 
-impl<K, V> Serialize for LinkedHashMap<K, V>
+impl<K, V, S> Serialize for LinkedHashMap<K, V, S>
 where
     K: Serialize + Hash + Eq,
     V: Serialize,
+    S: BuildHasher,
 {
-    fn serialize<S>(&self, serializer: S) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error>
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
     where
-        S: Serializer,
+        T: Serializer,
     {
         let mut ser_map = serializer.serialize_map(Some(self.len()))?;
         for (k, v) in self.iter() {
@@ -25,27 +40,157 @@ where
     }
 }
 
-pub struct LinkedHashMapVisitor<K, V> {
-    marker: PhantomData<LinkedHashMap<K, V>>,
+/// What deserializing does when a key appears more than once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Fail with an error. It names the key when set through
+    /// [reject_duplicate_keys](LinkedHashMapVisitor::reject_duplicate_keys).
+    Error,
+    /// Keep the first value and ignore later ones.
+    KeepFirst,
+    /// Keep the last value, at the position of the first occurrence. This is what
+    /// [insert](LinkedHashMap::insert) does, and the default.
+    #[default]
+    LastAtFirstPosition,
+    /// Keep the last value, at the position of the last occurrence.
+    LastAtLastPosition,
+}
+
+/// Set by `reject_duplicate_keys` to name the offending key.
+type DescribeFn<K> = fn(&K) -> String;
+
+struct OnDuplicate<K> {
+    policy: DuplicateKeys,
+    describe: Option<DescribeFn<K>>,
+}
+
+impl<K> Clone for OnDuplicate<K> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
-impl<K, V> LinkedHashMapVisitor<K, V> {
+impl<K> Copy for OnDuplicate<K> {}
+
+impl<K> OnDuplicate<K>
+where
+    K: Hash + Eq,
+{
+    fn insert<V, S, E>(self, map: &mut LinkedHashMap<K, V, S>, key: K, value: V) -> Result<(), E>
+    where
+        S: BuildHasher,
+        E: Error,
+    {
+        match self.policy {
+            DuplicateKeys::Error if map.contains(&key) => {
+                return Err(match self.describe {
+                    Some(describe) => E::custom(format!("duplicate key {}", describe(&key))),
+                    None => E::custom("duplicate key"),
+                });
+            }
+            DuplicateKeys::KeepFirst if map.contains(&key) => return Ok(()),
+            DuplicateKeys::LastAtLastPosition => {
+                map.remove(&key);
+            }
+            _ => {}
+        }
+        map.insert(key, value);
+        Ok(())
+    }
+}
+
+/// Most a size hint can make deserialization allocate up front. Hints come from the input, so
+/// a larger map grows as its entries actually arrive.
+const MAX_PREALLOC_BYTES: usize = 1024 * 1024;
+
+fn initial_capacity<K, V>(hint: Option<usize>, max_entries: Option<usize>) -> usize {
+    hint.unwrap_or(0)
+        .min(MAX_PREALLOC_BYTES / mem::size_of::<(K, V)>().max(1))
+        .min(max_entries.unwrap_or(usize::MAX))
+}
+
+fn check_entries<E: Error>(read: usize, max_entries: Option<usize>) -> Result<(), E> {
+    match max_entries {
+        Some(max) if read > max => {
+            Err(E::custom(format_args!("map has more than {} entries", max)))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub struct LinkedHashMapVisitor<K, V, S = RandomState> {
+    hasher: S,
+    on_duplicate: OnDuplicate<K>,
+    max_entries: Option<usize>,
+    marker: PhantomData<LinkedHashMap<K, V, S>>,
+}
+
+impl<K, V, S> LinkedHashMapVisitor<K, V, S>
+where
+    S: Default,
+{
     pub fn new() -> Self {
+        LinkedHashMapVisitor::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> Default for LinkedHashMapVisitor<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        LinkedHashMapVisitor::new()
+    }
+}
+
+impl<K, V, S> LinkedHashMapVisitor<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
         LinkedHashMapVisitor {
+            hasher,
+            on_duplicate: OnDuplicate {
+                policy: DuplicateKeys::default(),
+                describe: None,
+            },
+            max_entries: None,
             marker: PhantomData,
         }
     }
+
+    pub fn duplicate_keys(mut self, policy: DuplicateKeys) -> Self {
+        self.on_duplicate.policy = policy;
+        self
+    }
+
+    /// Like [DuplicateKeys::Error], with the offending key in the error message.
+    pub fn reject_duplicate_keys(mut self) -> Self
+    where
+        K: Debug,
+    {
+        self.on_duplicate = OnDuplicate {
+            policy: DuplicateKeys::Error,
+            describe: Some(|key| format!("{:?}", key)),
+        };
+        self
+    }
+
+    /// Fails once the input holds more than `max` entries, counting repeated keys, instead of
+    /// reading the rest of it.
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = Some(max);
+        self
+    }
 }
 
-impl<'de, K, V> Visitor<'de> for LinkedHashMapVisitor<K, V>
+impl<'de, K, V, S> Visitor<'de> for LinkedHashMapVisitor<K, V, S>
 where
     K: Deserialize<'de> + Eq + Hash,
     V: Deserialize<'de>,
+    S: BuildHasher,
 {
-    type Value = LinkedHashMap<K, V>;
+    type Value = LinkedHashMap<K, V, S>;
 
     fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-        write!(formatter, "a linked hash map")
+        write!(formatter, "a map or a sequence of key-value pairs")
     }
 
     #[inline]
@@ -53,7 +198,7 @@ where
     where
         E: serde::de::Error,
     {
-        Ok(LinkedHashMap::new())
+        Ok(LinkedHashMap::with_hasher(self.hasher))
     }
 
     #[inline]
@@ -61,25 +206,1067 @@ where
     where
         M: MapAccess<'de>,
     {
-        let mut values = LinkedHashMap::with_capacity(map.size_hint().unwrap_or(0));
+        check_entries(map.size_hint().unwrap_or(0), self.max_entries)?;
+        let capacity = initial_capacity::<K, V>(map.size_hint(), self.max_entries);
+        let mut values = LinkedHashMap::with_capacity_and_hasher(capacity, self.hasher);
 
+        let mut read = 0;
         while let Some((key, value)) = map.next_entry()? {
-            values.insert(key, value);
+            read += 1;
+            check_entries(read, self.max_entries)?;
+            self.on_duplicate.insert(&mut values, key, value)?;
+        }
+
+        Ok(values)
+    }
+
+    /// Accepts the sequence form written by [as_seq], as `[k, v]` pairs or
+    /// `{"key": k, "value": v}` entries.
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        check_entries(seq.size_hint().unwrap_or(0), self.max_entries)?;
+        let capacity = initial_capacity::<K, V>(seq.size_hint(), self.max_entries);
+        let mut values = LinkedHashMap::with_capacity_and_hasher(capacity, self.hasher);
+
+        let mut read = 0;
+        while let Some(as_seq::Pair(key, value)) = seq.next_element()? {
+            read += 1;
+            check_entries(read, self.max_entries)?;
+            self.on_duplicate.insert(&mut values, key, value)?;
         }
 
         Ok(values)
     }
 }
 
-impl<'de, K, V> Deserialize<'de> for LinkedHashMap<K, V>
+impl<'de, K, V, S> Deserialize<'de> for LinkedHashMap<K, V, S>
 where
     K: Deserialize<'de> + Eq + Hash,
     V: Deserialize<'de>,
+    S: BuildHasher + Default,
 {
-    fn deserialize<D>(deserializer: D) -> Result<LinkedHashMap<K, V>, D::Error>
+    fn deserialize<D>(deserializer: D) -> Result<LinkedHashMap<K, V, S>, D::Error>
     where
         D: Deserializer<'de>,
     {
         deserializer.deserialize_map(LinkedHashMapVisitor::new())
     }
 }
+
+/// Deserializes a [LinkedHashMap] that uses a hasher built beforehand, e.g. one with fixed
+/// keys, instead of `S::default()`, and optionally a [DuplicateKeys] policy and an entry limit.
+///
+/// ```rust
+/// use std::collections::hash_map::RandomState;
+/// use serde::de::DeserializeSeed;
+/// use linked_hash_map_rs::serde::LinkedHashMapSeed;
+///
+/// let mut json = serde_json::Deserializer::from_str(r#"{"b":2,"a":1}"#);
+/// let map = LinkedHashMapSeed::<String, i32, _>::new(RandomState::new())
+///     .deserialize(&mut json)
+///     .unwrap();
+/// assert_eq!(map.front(), Some((&"b".to_string(), &2)));
+///
+/// let mut json = serde_json::Deserializer::from_str(r#"{"a":1,"a":2}"#);
+/// let err = LinkedHashMapSeed::<String, i32, _>::new(RandomState::new())
+///     .reject_duplicate_keys()
+///     .deserialize(&mut json)
+///     .unwrap_err();
+/// assert!(err.to_string().starts_with(r#"duplicate key "a""#));
+/// ```
+pub struct LinkedHashMapSeed<K, V, S> {
+    visitor: LinkedHashMapVisitor<K, V, S>,
+}
+
+impl<K, V, S> LinkedHashMapSeed<K, V, S> {
+    pub fn new(hasher: S) -> Self {
+        LinkedHashMapSeed {
+            visitor: LinkedHashMapVisitor::with_hasher(hasher),
+        }
+    }
+
+    pub fn duplicate_keys(self, policy: DuplicateKeys) -> Self {
+        LinkedHashMapSeed {
+            visitor: self.visitor.duplicate_keys(policy),
+        }
+    }
+
+    /// Like [DuplicateKeys::Error], with the offending key in the error message.
+    pub fn reject_duplicate_keys(self) -> Self
+    where
+        K: Debug,
+    {
+        LinkedHashMapSeed {
+            visitor: self.visitor.reject_duplicate_keys(),
+        }
+    }
+
+    /// Fails once the input holds more than `max` entries, for input that is not trusted.
+    pub fn max_entries(self, max: usize) -> Self {
+        LinkedHashMapSeed {
+            visitor: self.visitor.max_entries(max),
+        }
+    }
+}
+
+impl<'de, K, V, S> DeserializeSeed<'de> for LinkedHashMapSeed<K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher,
+{
+    type Value = LinkedHashMap<K, V, S>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self.visitor)
+    }
+}
+
+/// A [LinkedHashMap] that fails to deserialize if any key appears twice, for input such as
+/// configuration where a repeated key is a mistake.
+///
+/// ```rust
+/// use linked_hash_map_rs::serde::UniqueKeys;
+///
+/// let json = r#"{"port":80,"port":8080}"#;
+/// let err = serde_json::from_str::<UniqueKeys<String, u32>>(json).err().unwrap();
+/// assert!(err.to_string().starts_with(r#"duplicate key "port""#));
+/// ```
+pub struct UniqueKeys<K, V, S = RandomState>(pub LinkedHashMap<K, V, S>);
+
+impl<K, V, S> Serialize for UniqueKeys<K, V, S>
+where
+    K: Serialize + Hash + Eq,
+    V: Serialize,
+    S: BuildHasher,
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for UniqueKeys<K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash + Debug,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_map(LinkedHashMapVisitor::new().reject_duplicate_keys())
+            .map(UniqueKeys)
+    }
+}
+
+/// Streams a serialized map into an [LruCache] of a fixed capacity, evicting the oldest entries
+/// as newer ones arrive, so only the last `capacity` entries of the input are ever held.
+///
+/// Accepts the map form and the [as_seq] forms, like [LinkedHashMap] does.
+///
+/// ```rust
+/// use serde::de::DeserializeSeed;
+/// use linked_hash_map_rs::serde::LruCacheSeed;
+///
+/// let mut json = serde_json::Deserializer::from_str(r#"{"a":1,"b":2,"c":3}"#);
+/// let mut cache = LruCacheSeed::<String, i32>::new(2).deserialize(&mut json).unwrap();
+/// assert_eq!(cache.len(), 2);
+/// assert_eq!(cache.get("a"), None);
+/// assert_eq!(cache.peek_lru(), Some((&"b".to_string(), &2)));
+/// ```
+pub struct LruCacheSeed<K, V, S = RandomState> {
+    capacity: usize,
+    hasher: S,
+    marker: PhantomData<LruCache<K, V, S>>,
+}
+
+impl<K, V> LruCacheSeed<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCacheSeed::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> LruCacheSeed<K, V, S> {
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        LruCacheSeed {
+            capacity,
+            hasher,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, K, V, S> DeserializeSeed<'de> for LruCacheSeed<K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher,
+{
+    type Value = LruCache<K, V, S>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(self)
+        } else {
+            deserializer.deserialize_map(self)
+        }
+    }
+}
+
+impl<'de, K, V, S> Visitor<'de> for LruCacheSeed<K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher,
+{
+    type Value = LruCache<K, V, S>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        write!(formatter, "a map or a sequence of key-value pairs")
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        Ok(LruCache::with_hasher(self.capacity, self.hasher))
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+    where
+        M: MapAccess<'de>,
+    {
+        let mut cache = LruCache::with_hasher(self.capacity, self.hasher);
+        while let Some((key, value)) = map.next_entry()? {
+            cache.insert(key, value);
+        }
+        Ok(cache)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut cache = LruCache::with_hasher(self.capacity, self.hasher);
+        while let Some(as_seq::Pair(key, value)) = seq.next_element()? {
+            cache.insert(key, value);
+        }
+        Ok(cache)
+    }
+}
+
+/// Serializes as `{"capacity": n, "entries": [[k, v, pinned], ...]}`, entries from least to
+/// most recently used.
+impl<K, V, S> Serialize for LruCache<K, V, S>
+where
+    K: Serialize + Hash + Eq,
+    V: Serialize,
+    S: BuildHasher,
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        let mut cache = serializer.serialize_struct("LruCache", 2)?;
+        cache.serialize_field("capacity", &self.capacity())?;
+        cache.serialize_field("entries", &lru_entries(self))?;
+        cache.end()
+    }
+}
+
+/// Restores the recency order and pins. If there are more entries than fit, the least recently
+/// used unpinned ones are dropped.
+impl<'de, K, V, S> Deserialize<'de> for LruCache<K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parts = CacheParts::deserialize(deserializer, "LruCache", LRU_FIELDS)?;
+        let mut cache = LruCache::with_hasher(parts.capacity, S::default());
+        fill_lru(&mut cache, parts.entries);
+        Ok(cache)
+    }
+}
+
+type LruEntries<K, V> = Vec<(K, V, bool)>;
+
+fn lru_entries<K, V, S>(cache: &LruCache<K, V, S>) -> LruEntries<&K, &V>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    cache
+        .iter()
+        .map(|(k, v)| (k, v, cache.is_pinned(k)))
+        .collect()
+}
+
+/// Appends `entries` as the most recently used, then evicts until the cache fits.
+fn fill_lru<K, V, S>(cache: &mut LruCache<K, V, S>, entries: LruEntries<K, V>)
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    for (k, v, pinned) in entries {
+        cache.restore(k, v, pinned);
+    }
+    cache.set_capacity(cache.capacity());
+}
+
+const LRU_FIELDS: &[&str] = &["capacity", "entries"];
+const TTL_FIELDS: &[&str] = &["capacity", "ttl", "entries"];
+const SHARDED_FIELDS: &[&str] = &["shards", "capacity", "entries"];
+
+/// The most shards a deserialized [ShardedLru] may ask for. Each one is allocated up front, so
+/// a corrupt count must not be trusted.
+const MAX_SHARDS: usize = 1 << 16;
+
+/// The fields of a serialized cache, read before the cache is built. Only the fields the cache
+/// was asked for are set.
+struct CacheParts<E> {
+    shards: usize,
+    capacity: usize,
+    ttl: Duration,
+    entries: Vec<E>,
+}
+
+impl<'de, E> CacheParts<E>
+where
+    E: Deserialize<'de>,
+{
+    fn deserialize<D>(
+        deserializer: D,
+        name: &'static str,
+        fields: &'static [&'static str],
+    ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct(
+            name,
+            fields,
+            CachePartsVisitor {
+                name,
+                fields,
+                marker: PhantomData,
+            },
+        )
+    }
+}
+
+struct CachePartsVisitor<E> {
+    name: &'static str,
+    fields: &'static [&'static str],
+    marker: PhantomData<E>,
+}
+
+impl<'de, E> Visitor<'de> for CachePartsVisitor<E>
+where
+    E: Deserialize<'de>,
+{
+    type Value = CacheParts<E>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        write!(formatter, "a {} with fields {:?}", self.name, self.fields)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut parts = CacheParts {
+            shards: 0,
+            capacity: 0,
+            ttl: Duration::ZERO,
+            entries: Vec::new(),
+        };
+        for (i, &field) in self.fields.iter().enumerate() {
+            let read = match field {
+                "shards" => seq.next_element()?.map(|n| parts.shards = n),
+                "capacity" => seq.next_element()?.map(|n| parts.capacity = n),
+                "ttl" => seq.next_element()?.map(|ttl| parts.ttl = ttl),
+                _ => seq.next_element()?.map(|entries| parts.entries = entries),
+            };
+            if read.is_none() {
+                return Err(Error::invalid_length(i, &self));
+            }
+        }
+        Ok(parts)
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+    where
+        M: MapAccess<'de>,
+    {
+        let mut parts = CacheParts {
+            shards: 0,
+            capacity: 0,
+            ttl: Duration::ZERO,
+            entries: Vec::new(),
+        };
+        let mut seen = Vec::with_capacity(self.fields.len());
+        let fields = CacheFieldVisitor {
+            fields: self.fields,
+        };
+        while let Some(field) = map.next_key_seed(fields)? {
+            let name = field.name();
+            if seen.contains(&name) {
+                return Err(Error::duplicate_field(name));
+            }
+            seen.push(name);
+            match field {
+                CacheField::Shards => parts.shards = map.next_value()?,
+                CacheField::Capacity => parts.capacity = map.next_value()?,
+                CacheField::Ttl => parts.ttl = map.next_value()?,
+                CacheField::Entries => parts.entries = map.next_value()?,
+            }
+        }
+        match self.fields.iter().find(|field| !seen.contains(field)) {
+            Some(missing) => Err(Error::missing_field(missing)),
+            None => Ok(parts),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CacheField {
+    Shards,
+    Capacity,
+    Ttl,
+    Entries,
+}
+
+impl CacheField {
+    fn name(self) -> &'static str {
+        match self {
+            CacheField::Shards => "shards",
+            CacheField::Capacity => "capacity",
+            CacheField::Ttl => "ttl",
+            CacheField::Entries => "entries",
+        }
+    }
+}
+
+/// Reads a field identifier of the cache whose fields are `fields`, given by name or, in
+/// formats that send identifiers as integers, by position.
+#[derive(Clone, Copy)]
+struct CacheFieldVisitor {
+    fields: &'static [&'static str],
+}
+
+impl<'de> DeserializeSeed<'de> for CacheFieldVisitor {
+    type Value = CacheField;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for CacheFieldVisitor {
+    type Value = CacheField;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        write!(formatter, "one of the fields {:?}", self.fields)
+    }
+
+    fn visit_u64<E>(self, index: u64) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        match self.fields.get(index as usize) {
+            Some(field) => self.visit_str(field),
+            None => Err(Error::invalid_value(Unexpected::Unsigned(index), &self)),
+        }
+    }
+
+    fn visit_str<E>(self, field: &str) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        if !self.fields.contains(&field) {
+            return Err(Error::unknown_field(field, self.fields));
+        }
+        Ok(match field {
+            "shards" => CacheField::Shards,
+            "capacity" => CacheField::Capacity,
+            "ttl" => CacheField::Ttl,
+            _ => CacheField::Entries,
+        })
+    }
+
+    fn visit_bytes<E>(self, field: &[u8]) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        match std::str::from_utf8(field) {
+            Ok(field) => self.visit_str(field),
+            Err(_) => Err(Error::unknown_field(
+                &String::from_utf8_lossy(field),
+                self.fields,
+            )),
+        }
+    }
+}
+
+/// Serializes like [LruCache], after applying the buffered reads.
+impl<K, V, S> Serialize for ConcurrentLru<K, V, S>
+where
+    K: Serialize + Hash + Eq,
+    V: Serialize,
+    S: BuildHasher,
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        self.write().serialize(serializer)
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for ConcurrentLru<K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parts = CacheParts::deserialize(deserializer, "LruCache", LRU_FIELDS)?;
+        let cache = ConcurrentLru::with_hasher(parts.capacity, S::default());
+        fill_lru(&mut cache.write(), parts.entries);
+        Ok(cache)
+    }
+}
+
+/// Serializes as `{"shards": n, "capacity": c, "entries": [[k, v, pinned], ...]}`, with the
+/// capacity per shard and every shard's entries from least to most recently used, shard after
+/// shard.
+///
+/// Every shard stays locked until the entries are written, so they are consistent even while
+/// the cache is in use.
+impl<K, V, S> Serialize for ShardedLru<K, V, S>
+where
+    K: Serialize + Hash + Eq,
+    V: Serialize,
+    S: BuildHasher + Clone,
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        let guards = self.shards.iter().map(lock).collect::<Vec<_>>();
+        let entries = guards
+            .iter()
+            .flat_map(|shard| lru_entries(&shard.cache))
+            .collect::<Vec<_>>();
+        let mut cache = serializer.serialize_struct("ShardedLru", 3)?;
+        cache.serialize_field("shards", &self.shard_count())?;
+        cache.serialize_field("capacity", &guards[0].cache.capacity())?;
+        cache.serialize_field("entries", &entries)?;
+        cache.end()
+    }
+}
+
+/// Keys are hashed to shards again, keeping their relative recency order and pins. If a shard
+/// gets more entries than fit, its least recently used unpinned ones are dropped.
+impl<'de, K, V, S> Deserialize<'de> for ShardedLru<K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher + Clone + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parts: CacheParts<(K, V, bool)> =
+            CacheParts::deserialize(deserializer, "ShardedLru", SHARDED_FIELDS)?;
+        if parts.shards == 0 || parts.shards > MAX_SHARDS {
+            return Err(Error::invalid_value(
+                Unexpected::Unsigned(parts.shards as u64),
+                &"between 1 and 65536 shards",
+            ));
+        }
+        let cache = ShardedLru::with_hasher(parts.shards, parts.capacity, S::default());
+        for (k, v, pinned) in parts.entries {
+            cache.shard(&k).cache.restore(k, v, pinned);
+        }
+        for shard in cache.shards.iter() {
+            let mut shard = lock(shard);
+            let capacity = shard.cache.capacity();
+            shard.cache.set_capacity(capacity);
+        }
+        Ok(cache)
+    }
+}
+
+/// Serializes as `{"capacity": n, "entries": [[k, v, dirty], ...]}`, entries from least to most
+/// recently used. The flusher is not written.
+impl<K, V, F, S> Serialize for WriteBackCache<K, V, F, S>
+where
+    K: Serialize + Hash + Eq,
+    V: Serialize,
+    F: Flusher<K, V>,
+    S: BuildHasher,
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        let entries = self.iter().collect::<Vec<_>>();
+        let mut cache = serializer.serialize_struct("WriteBackCache", 2)?;
+        cache.serialize_field("capacity", &self.capacity())?;
+        cache.serialize_field("entries", &entries)?;
+        cache.end()
+    }
+}
+
+/// Restores the recency order and dirty flags, with `F::default()` as the flusher;
+/// [WriteBackCacheSeed] supplies another one.
+impl<'de, K, V, F, S> Deserialize<'de> for WriteBackCache<K, V, F, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    F: Flusher<K, V> + Default,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        WriteBackCacheSeed::with_hasher(F::default(), S::default()).deserialize(deserializer)
+    }
+}
+
+/// Deserializes a [WriteBackCache] that writes to the given flusher.
+///
+/// If there are more entries than fit, the least recently used ones are evicted, and dirty ones
+/// among them are flushed; a failed flush fails deserialization.
+///
+/// ```rust
+/// use serde::de::DeserializeSeed;
+/// use linked_hash_map_rs::serde::WriteBackCacheSeed;
+/// use linked_hash_map_rs::MemoryStore;
+///
+/// let mut store = MemoryStore::<u32, String>::new();
+/// let json = r#"{"capacity":1,"entries":[[1,"a",true],[2,"b",false]]}"#;
+/// let mut json = serde_json::Deserializer::from_str(json);
+/// let cache = WriteBackCacheSeed::new(&mut store).deserialize(&mut json).unwrap();
+/// assert!(!cache.is_dirty(&2));
+/// drop(cache);
+/// assert_eq!(store.get(&1), Some(&"a".to_string()));
+/// ```
+pub struct WriteBackCacheSeed<K, V, F, S = RandomState> {
+    flusher: F,
+    hasher: S,
+    marker: PhantomData<WriteBackCache<K, V, F, S>>,
+}
+
+impl<K, V, F> WriteBackCacheSeed<K, V, F> {
+    pub fn new(flusher: F) -> Self {
+        WriteBackCacheSeed::with_hasher(flusher, RandomState::new())
+    }
+}
+
+impl<K, V, F, S> WriteBackCacheSeed<K, V, F, S> {
+    pub fn with_hasher(flusher: F, hasher: S) -> Self {
+        WriteBackCacheSeed {
+            flusher,
+            hasher,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, K, V, F, S> DeserializeSeed<'de> for WriteBackCacheSeed<K, V, F, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    F: Flusher<K, V>,
+    S: BuildHasher,
+{
+    type Value = WriteBackCache<K, V, F, S>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parts: CacheParts<(K, V, bool)> =
+            CacheParts::deserialize(deserializer, "WriteBackCache", LRU_FIELDS)?;
+        let mut cache = WriteBackCache::with_hasher(parts.capacity, self.flusher, self.hasher);
+        for (k, v, dirty) in parts.entries {
+            cache.restore(k, v, dirty);
+        }
+        cache.set_capacity(parts.capacity).map_err(|_| {
+            D::Error::custom("failed to flush an entry evicted to fit the capacity")
+        })?;
+        Ok(cache)
+    }
+}
+
+/// Serializes as `{"capacity": n, "ttl": d, "entries": [[k, v, age], ...]}`, with unexpired
+/// entries from least to most recently used and their age as a [Duration].
+impl<K, V, S, C> Serialize for TtlCache<K, V, S, C>
+where
+    K: Serialize + Hash + Eq,
+    V: Serialize,
+    S: BuildHasher,
+    C: Clock,
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        let now = self.clock().now();
+        let entries = self
+            .cache
+            .iter()
+            .map(|(k, (inserted_at, v))| (k, v, now.saturating_duration_since(*inserted_at)))
+            .filter(|(_, _, age)| *age < self.ttl())
+            .collect::<Vec<_>>();
+        let mut cache = serializer.serialize_struct("TtlCache", 3)?;
+        cache.serialize_field("capacity", &self.capacity())?;
+        cache.serialize_field("ttl", &self.ttl())?;
+        cache.serialize_field("entries", &entries)?;
+        cache.end()
+    }
+}
+
+/// Restores entries with the age they were serialized with, measured against `C::default()`;
+/// [TtlCacheSeed] supplies another clock.
+impl<'de, K, V, S, C> Deserialize<'de> for TtlCache<K, V, S, C>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+    C: Clock + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        TtlCacheSeed::with_hasher_and_clock(S::default(), C::default()).deserialize(deserializer)
+    }
+}
+
+/// Deserializes a [TtlCache] that measures time with the given clock. Entries keep the age they
+/// were serialized with, so time spent serialized does not count toward their expiry.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use serde::de::DeserializeSeed;
+/// use linked_hash_map_rs::serde::TtlCacheSeed;
+/// use linked_hash_map_rs::ManualClock;
+///
+/// let clock = ManualClock::new();
+/// let json = concat!(
+///     r#"{"capacity":2,"ttl":{"secs":60,"nanos":0},"#,
+///     r#""entries":[[1,"a",{"secs":50,"nanos":0}]]}"#,
+/// );
+/// let mut json = serde_json::Deserializer::from_str(json);
+/// let mut cache = TtlCacheSeed::<u32, String, _, _>::with_clock(clock.clone())
+///     .deserialize(&mut json)
+///     .unwrap();
+/// assert_eq!(cache.age(&1), Some(Duration::from_secs(50)));
+/// clock.advance(Duration::from_secs(10));
+/// assert_eq!(cache.get(&1), None);
+/// ```
+pub struct TtlCacheSeed<K, V, S = RandomState, C = SystemClock> {
+    hasher: S,
+    clock: C,
+    marker: PhantomData<TtlCache<K, V, S, C>>,
+}
+
+impl<K, V, C> TtlCacheSeed<K, V, RandomState, C> {
+    pub fn with_clock(clock: C) -> Self {
+        TtlCacheSeed::with_hasher_and_clock(RandomState::new(), clock)
+    }
+}
+
+impl<K, V, S, C> TtlCacheSeed<K, V, S, C> {
+    pub fn with_hasher_and_clock(hasher: S, clock: C) -> Self {
+        TtlCacheSeed {
+            hasher,
+            clock,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, K, V, S, C> DeserializeSeed<'de> for TtlCacheSeed<K, V, S, C>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher,
+    C: Clock,
+{
+    type Value = TtlCache<K, V, S, C>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parts: CacheParts<(K, V, Duration)> =
+            CacheParts::deserialize(deserializer, "TtlCache", TTL_FIELDS)?;
+        let mut cache =
+            TtlCache::with_hasher_and_clock(parts.capacity, parts.ttl, self.hasher, self.clock);
+        let now = cache.clock().now();
+        for (key, value, age) in parts.entries {
+            // An age reaching back before the clock's epoch cannot be represented; such an
+            // entry would be long expired on any realistic TTL.
+            if let Some(inserted_at) = now.checked_sub(age) {
+                cache.cache.restore(key, (inserted_at, value), false);
+            }
+        }
+        // Keep the newest entries if there are more than fit.
+        cache.set_capacity(parts.capacity);
+        Ok(cache)
+    }
+}
+
+/// Serializes a [LinkedHashMap] as a sequence of `[k, v]` pairs, for formats that reorder map
+/// entries or only allow string keys.
+///
+/// Use it with `#[serde(with = "linked_hash_map_rs::serde::as_seq")]`, or
+/// [as_seq::entries] for `{"key": k, "value": v}` objects instead of pairs. Deserializing
+/// accepts either sequence form as well as a plain map in self-describing formats.
+///
+/// ```rust
+/// use linked_hash_map_rs::LinkedHashMap;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Routes {
+///     #[serde(with = "linked_hash_map_rs::serde::as_seq")]
+///     by_port: LinkedHashMap<u16, String>,
+/// }
+///
+/// let mut by_port = LinkedHashMap::new();
+/// by_port.insert(8080, "api".to_string());
+/// by_port.insert(80, "web".to_string());
+/// let json = serde_json::to_string(&Routes { by_port }).unwrap();
+/// assert_eq!(json, r#"{"by_port":[[8080,"api"],[80,"web"]]}"#);
+///
+/// let routes: Routes = serde_json::from_str(r#"{"by_port":{"80":"web"}}"#).unwrap();
+/// assert_eq!(routes.by_port.get(&80), Some(&"web".to_string()));
+/// ```
+pub mod as_seq {
+    use std::fmt::Formatter;
+    use std::hash::{BuildHasher, Hash};
+    use std::marker::PhantomData;
+
+    use serde::de::{Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+    use serde::ser::{SerializeSeq, SerializeStruct};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::LinkedHashMapVisitor;
+    use crate::LinkedHashMap;
+
+    pub fn serialize<K, V, S, T>(
+        map: &LinkedHashMap<K, V, S>,
+        serializer: T,
+    ) -> Result<T::Ok, T::Error>
+    where
+        K: Serialize + Hash + Eq,
+        V: Serialize,
+        S: BuildHasher,
+        T: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(map.len()))?;
+        for pair in map.iter() {
+            seq.serialize_element(&pair)?;
+        }
+        seq.end()
+    }
+
+    /// Reads either sequence form, or a map. Formats that are not self-describing only
+    /// support the sequence form.
+    pub fn deserialize<'de, K, V, S, D>(deserializer: D) -> Result<LinkedHashMap<K, V, S>, D::Error>
+    where
+        K: Deserialize<'de> + Hash + Eq,
+        V: Deserialize<'de>,
+        S: BuildHasher + Default,
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(LinkedHashMapVisitor::new())
+        } else {
+            deserializer.deserialize_seq(LinkedHashMapVisitor::new())
+        }
+    }
+
+    /// Like [as_seq](self), but writes each entry as a `{"key": k, "value": v}` object.
+    pub mod entries {
+        use std::hash::{BuildHasher, Hash};
+
+        use serde::ser::SerializeSeq;
+        use serde::{Serialize, Serializer};
+
+        use super::Entry;
+        use crate::LinkedHashMap;
+
+        pub use super::deserialize;
+
+        pub fn serialize<K, V, S, T>(
+            map: &LinkedHashMap<K, V, S>,
+            serializer: T,
+        ) -> Result<T::Ok, T::Error>
+        where
+            K: Serialize + Hash + Eq,
+            V: Serialize,
+            S: BuildHasher,
+            T: Serializer,
+        {
+            let mut seq = serializer.serialize_seq(Some(map.len()))?;
+            for (key, value) in map.iter() {
+                seq.serialize_element(&Entry { key, value })?;
+            }
+            seq.end()
+        }
+    }
+
+    struct Entry<'a, K, V> {
+        key: &'a K,
+        value: &'a V,
+    }
+
+    impl<K: Serialize, V: Serialize> Serialize for Entry<'_, K, V> {
+        fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+        where
+            T: Serializer,
+        {
+            let mut entry = serializer.serialize_struct("Entry", 2)?;
+            entry.serialize_field("key", self.key)?;
+            entry.serialize_field("value", self.value)?;
+            entry.end()
+        }
+    }
+
+    /// One element of the sequence form, read from either a pair or an entry object.
+    pub(crate) struct Pair<K, V>(pub(crate) K, pub(crate) V);
+
+    impl<'de, K, V> Deserialize<'de> for Pair<K, V>
+    where
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            // Pairs and entries are both plain tuples in formats that are not self-describing.
+            if deserializer.is_human_readable() {
+                deserializer.deserialize_any(PairVisitor(PhantomData))
+            } else {
+                deserializer.deserialize_tuple(2, PairVisitor(PhantomData))
+            }
+        }
+    }
+
+    struct PairVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K, V> Visitor<'de> for PairVisitor<K, V>
+    where
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        type Value = Pair<K, V>;
+
+        fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+            write!(formatter, "a [key, value] pair or a {{key, value}} entry")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let key = seq
+                .next_element()?
+                .ok_or_else(|| Error::invalid_length(0, &self))?;
+            let value = seq
+                .next_element()?
+                .ok_or_else(|| Error::invalid_length(1, &self))?;
+            if seq.next_element::<IgnoredAny>()?.is_some() {
+                return Err(Error::invalid_length(3, &self));
+            }
+            Ok(Pair(key, value))
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut key = None;
+            let mut value = None;
+            while let Some(field) = map.next_key()? {
+                match field {
+                    Field::Key if key.is_some() => return Err(Error::duplicate_field("key")),
+                    Field::Key => key = Some(map.next_value()?),
+                    Field::Value if value.is_some() => return Err(Error::duplicate_field("value")),
+                    Field::Value => value = Some(map.next_value()?),
+                }
+            }
+            let key = key.ok_or_else(|| Error::missing_field("key"))?;
+            let value = value.ok_or_else(|| Error::missing_field("value"))?;
+            Ok(Pair(key, value))
+        }
+    }
+
+    enum Field {
+        Key,
+        Value,
+    }
+
+    impl<'de> Deserialize<'de> for Field {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_identifier(FieldVisitor)
+        }
+    }
+
+    struct FieldVisitor;
+
+    impl<'de> Visitor<'de> for FieldVisitor {
+        type Value = Field;
+
+        fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+            write!(formatter, "`key` or `value`")
+        }
+
+        fn visit_str<E>(self, field: &str) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            match field {
+                "key" => Ok(Field::Key),
+                "value" => Ok(Field::Value),
+                _ => Err(Error::unknown_field(field, &["key", "value"])),
+            }
+        }
+    }
+}
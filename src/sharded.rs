@@ -0,0 +1,278 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::AddAssign;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::lru::EvictionListener;
+use crate::{CacheFullError, LruCache};
+
+/// Hit, miss and eviction counters of a cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl AddAssign for CacheStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.hits += rhs.hits;
+        self.misses += rhs.misses;
+        self.evictions += rhs.evictions;
+    }
+}
+
+/// The cache-wide eviction listener. Every shard holds its own handle, so a shard without one
+/// takes no lock beyond its own.
+type SharedListener<K, V> = Arc<Mutex<EvictionListener<K, V>>>;
+
+pub(crate) struct Shard<K, V, S> {
+    pub(crate) cache: LruCache<K, V, S>,
+    stats: CacheStats,
+    listener: Option<SharedListener<K, V>>,
+}
+
+/// A thread-safe LRU cache split into independently locked [LruCache] shards.
+///
+/// Each key is hashed to one shard, so threads working on different shards never contend on
+/// the same lock. Recency and capacity are tracked per shard: an insert evicts the least
+/// recently used entry of its own shard, not of the whole cache.
+///
+/// ```rust
+/// use linked_hash_map_rs::ShardedLru;
+///
+/// let cache = ShardedLru::new(4, 100);
+/// cache.insert("a", 1);
+/// assert_eq!(cache.get(&"a"), Some(1));
+/// assert_eq!(cache.stats().hits, 1);
+/// ```
+pub struct ShardedLru<K, V, S = RandomState> {
+    pub(crate) shards: Box<[Mutex<Shard<K, V, S>>]>,
+    hasher: S,
+}
+
+impl<K, V> ShardedLru<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new(shards: usize, capacity_per_shard: usize) -> Self {
+        ShardedLru::with_hasher(shards, capacity_per_shard, RandomState::new())
+    }
+}
+
+impl<K, V, S> ShardedLru<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_hasher(shards: usize, capacity_per_shard: usize, hasher: S) -> Self {
+        assert!(shards > 0, "ShardedLru needs at least one shard");
+        ShardedLru {
+            shards: (0..shards)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        cache: LruCache::with_hasher(capacity_per_shard, hasher.clone()),
+                        stats: CacheStats::default(),
+                        listener: None,
+                    })
+                })
+                .collect(),
+            hasher,
+        }
+    }
+
+    pub(crate) fn shard<Q>(&self, key: &Q) -> MutexGuard<'_, Shard<K, V, S>>
+    where
+        Q: Hash + ?Sized,
+    {
+        // The shards hash with the same hasher, and `HashMap` picks buckets from the low bits,
+        // so take the shard from the high half to keep each shard's keys evenly spread.
+        let index = (self.hasher.hash_one(key) >> 32) as usize % self.shards.len();
+        lock(&self.shards[index])
+    }
+
+    #[inline]
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Calls `listener` with every entry evicted to make room in any shard.
+    ///
+    /// The listener runs after the shard lock is released, so it may use the cache, but calls
+    /// from different threads take turns. An eviction caused from inside the listener, e.g. by
+    /// inserting into a full shard, waits for that turn and deadlocks.
+    pub fn set_eviction_listener<F>(&self, listener: F)
+    where
+        F: FnMut(&K, &V) + Send + Sync + 'static,
+    {
+        let listener: SharedListener<K, V> = Arc::new(Mutex::new(Box::new(listener)));
+        for shard in self.shards.iter() {
+            lock(shard).listener = Some(Arc::clone(&listener));
+        }
+    }
+
+    /// Changes the capacity of every shard, evicting least recently used entries until each
+    /// shard fits.
+    pub fn set_capacity(&self, capacity_per_shard: usize) {
+        for shard in self.shards.iter() {
+            let mut evicted = Vec::new();
+            let listener = {
+                let mut shard = lock(shard);
+                while shard.cache.len() > capacity_per_shard {
+                    match shard.cache.pop_lru() {
+                        Some(entry) => evicted.push(entry),
+                        None => break,
+                    }
+                }
+                shard.cache.set_capacity(capacity_per_shard);
+                shard.stats.evictions += evicted.len() as u64;
+                shard.listener.clone()
+            };
+            if let Some(listener) = listener {
+                for (key, value) in &evicted {
+                    notify(&listener, key, value);
+                }
+            }
+        }
+    }
+
+    pub fn shrink_to_fit(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).cache.shrink_to_fit()
+        }
+    }
+
+    /// Total capacity of all shards.
+    pub fn capacity(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| lock(shard).cache.capacity())
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| lock(shard).cache.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| lock(shard).cache.is_empty())
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).cache.contains(key)
+    }
+
+    /// Returns a clone of the value for `key` and marks it as most recently used.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.get_with(key, V::clone)
+    }
+
+    /// Calls `f` on the value for `key` under the shard lock and marks it as most recently used.
+    pub fn get_with<Q, F, R>(&self, key: &Q, f: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&V) -> R,
+    {
+        let mut shard = self.shard(key);
+        let result = shard.cache.get(key).map(f);
+        match result {
+            Some(_) => shard.stats.hits += 1,
+            None => shard.stats.misses += 1,
+        }
+        result
+    }
+
+    /// Inserts into the key's shard, returning the entry evicted from that shard, if any.
+    ///
+    /// Like [LruCache::insert], hands the new entry back if the shard has nothing to evict.
+    pub fn insert(&self, key: K, value: V) -> Option<(K, V)> {
+        self.try_insert(key, value)
+            .unwrap_or_else(|err| Some((err.key, err.value)))
+    }
+
+    /// Inserts into the key's shard, failing if the shard is full and every entry is pinned.
+    pub fn try_insert(&self, key: K, value: V) -> Result<Option<(K, V)>, CacheFullError<K, V>> {
+        let (evicted, listener) = {
+            let mut shard = self.shard(&key);
+            match shard.cache.try_insert(key, value)? {
+                Some(evicted) => {
+                    shard.stats.evictions += 1;
+                    (evicted, shard.listener.clone())
+                }
+                None => return Ok(None),
+            }
+        };
+        if let Some(listener) = listener {
+            notify(&listener, &evicted.0, &evicted.1);
+        }
+        Ok(Some(evicted))
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).cache.remove(key)
+    }
+
+    /// Protects `key` from eviction. Returns whether the key is cached.
+    pub fn pin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).cache.pin(key)
+    }
+
+    /// Makes `key` evictable again. Returns whether the key is cached.
+    pub fn unpin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).cache.unpin(key)
+    }
+
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).cache.clear()
+        }
+    }
+
+    /// Counters summed over all shards.
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats::default();
+        for shard in self.shards.iter() {
+            stats += lock(shard).stats;
+        }
+        stats
+    }
+}
+
+fn notify<K, V>(listener: &SharedListener<K, V>, key: &K, value: &V) {
+    (*lock(listener))(key, value)
+}
+
+/// Shard operations leave the cache consistent even if user code panics under the lock,
+/// so poisoning is ignored.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
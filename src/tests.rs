@@ -1,5 +1,12 @@
-use crate::LinkedHashMap;
+use crate::{
+    CacheFullError, CacheStats, ConcurrentLru, Flusher, LinkedHashMap, Loader, LoadingCache,
+    DeferredExecutor, ImmediateExecutor, LruCache, ManualClock, MemoryStore, RefreshingCache,
+    ShardedLru, TtlCache, WriteBackCache,
+};
 use ahash::RandomState;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn test_with_capacity_and_hasher() {
@@ -79,6 +86,7 @@ fn test_push_and_pop() {
 }
 
 #[test]
+#[allow(clippy::option_map_unit_fn)]
 fn test_insert_get_and_remove() {
     let mut map = LinkedHashMap::new();
 
@@ -150,10 +158,1459 @@ fn test_debug() {
     assert_eq!(format!("{:?}", map), r###"{1: "a"}"###);
 }
 
+#[test]
+fn test_lru() {
+    let mut cache = LruCache::new(2);
+    assert_eq!(cache.insert(1, "a"), None);
+    assert_eq!(cache.insert(2, "b"), None);
+    assert_eq!(cache.get(&1), Some(&"a"));
+    assert_eq!(cache.insert(3, "c"), Some((2, "b")));
+    assert!(!cache.contains(&2));
+
+    assert_eq!(cache.insert(1, "A"), None);
+    assert_eq!(cache.peek_lru(), Some((&3, &"c")));
+    assert_eq!(cache.peek(&3), Some(&"c"));
+    assert_eq!(cache.insert(4, "d"), Some((3, "c")));
+    assert_eq!(
+        cache.iter().map(|(k, _)| *k).collect::<Vec<i32>>(),
+        vec![1, 4]
+    );
+
+    if let Some(v) = cache.get_mut(&1) {
+        *v = "AA";
+    }
+    assert_eq!(cache.pop_lru(), Some((4, "d")));
+    assert_eq!(cache.remove(&1), Some((1, "AA")));
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_lru_pin() {
+    let mut cache = LruCache::new(3);
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    cache.insert(3, "c");
+    assert!(cache.pin(&1));
+    assert!(cache.pin(&1));
+    assert!(!cache.pin(&4));
+    assert!(cache.is_pinned(&1));
+    assert_eq!(cache.pinned_len(), 1);
+
+    assert_eq!(cache.peek_lru(), Some((&2, &"b")));
+    assert_eq!(cache.insert(4, "d"), Some((2, "b")));
+    // The pinned entry kept its place at the front.
+    assert_eq!(
+        cache.iter().map(|(k, _)| *k).collect::<Vec<i32>>(),
+        vec![1, 3, 4]
+    );
+
+    cache.pin(&3);
+    cache.pin(&4);
+    assert_eq!(cache.pop_lru(), None);
+    assert_eq!(
+        cache.try_insert(5, "e"),
+        Err(CacheFullError {
+            key: 5,
+            value: "e"
+        })
+    );
+    assert_eq!(cache.insert(5, "e"), Some((5, "e")));
+    assert_eq!(cache.try_insert(1, "A"), Ok(None));
+
+    assert!(cache.unpin(&1));
+    assert!(!cache.is_pinned(&1));
+    assert_eq!(cache.remove(&3), Some((3, "c")));
+    assert_eq!(cache.pinned_len(), 1);
+    assert_eq!(cache.try_insert(6, "f"), Ok(None));
+    assert_eq!(cache.try_insert(7, "g"), Ok(Some((1, "A"))));
+    cache.clear();
+    assert_eq!(cache.pinned_len(), 0);
+}
+
+#[test]
+fn test_lru_set_capacity() {
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let mut cache = LruCache::new(4);
+    {
+        let evicted = Arc::clone(&evicted);
+        cache.set_eviction_listener(move |k, v| evicted.lock().unwrap().push((*k, *v)));
+    }
+    for i in 0..5 {
+        cache.insert(i, i * 10);
+    }
+    assert_eq!(*evicted.lock().unwrap(), vec![(0, 0)]);
+
+    cache.pin(&2);
+    cache.set_capacity(2);
+    assert_eq!(cache.capacity(), 2);
+    assert_eq!(*evicted.lock().unwrap(), vec![(0, 0), (1, 10), (3, 30)]);
+    assert_eq!(
+        cache.iter().map(|(k, _)| *k).collect::<Vec<i32>>(),
+        vec![2, 4]
+    );
+
+    cache.remove(&4);
+    cache.set_capacity(0);
+    assert_eq!(cache.len(), 1);
+    cache.unpin(&2);
+    cache.set_capacity(0);
+    assert!(cache.is_empty());
+    cache.shrink_to_fit();
+
+    cache.set_capacity(3);
+    for i in 0..3 {
+        assert_eq!(cache.insert(i, i), None);
+    }
+    assert_eq!(cache.len(), 3);
+    // Explicit removal is not an eviction.
+    cache.remove(&0);
+    cache.pop_lru();
+    assert_eq!(evicted.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn test_lru_zero_capacity() {
+    let mut cache = LruCache::new(0);
+    assert_eq!(cache.insert(1, "a"), Some((1, "a")));
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_ttl() {
+    let clock = ManualClock::new();
+    let mut cache = TtlCache::with_clock(2, Duration::from_secs(20), clock.clone());
+    cache.insert(1, "a");
+    clock.advance(Duration::from_secs(15));
+    assert_eq!(cache.get(&1), Some(&"a"));
+    assert_eq!(cache.age(&1), Some(Duration::from_secs(15)));
+    clock.advance(Duration::from_secs(5));
+    assert!(!cache.contains(&1));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get(&1), None);
+    assert!(cache.is_empty());
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let mut cache = TtlCache::new(2, Duration::from_secs(60));
+    {
+        let evicted = Arc::clone(&evicted);
+        cache.set_eviction_listener(move |k, _| evicted.lock().unwrap().push(*k));
+    }
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    assert_eq!(cache.insert(3, "c"), Some((1, "a")));
+    cache.set_capacity(1);
+    cache.shrink_to_fit();
+    assert_eq!(*evicted.lock().unwrap(), vec![1, 2]);
+    assert_eq!(cache.remove(&3), Some((3, "c")));
+}
+
+#[test]
+fn test_ttl_prefers_expired_victim() {
+    let clock = ManualClock::new();
+    let mut cache = TtlCache::with_clock(2, Duration::from_secs(60), clock.clone());
+    cache.insert(1, "a");
+    clock.advance(Duration::from_secs(30));
+    cache.insert(2, "b");
+    assert_eq!(cache.get(&1), Some(&"a"));
+
+    // 2 is the least recently used entry but still live, while 1 has expired.
+    clock.advance(Duration::from_secs(40));
+    assert_eq!(cache.insert(3, "c"), None);
+    assert_eq!(cache.get(&2), Some(&"b"));
+    assert_eq!(cache.get(&3), Some(&"c"));
+    assert_eq!(cache.len(), 2);
+
+    // With nothing expired, the least recently used entry goes as before.
+    assert_eq!(cache.insert(4, "d"), Some((2, "b")));
+}
+
+#[test]
+fn test_sharded_lru() {
+    let cache = ShardedLru::with_hasher(4, 2, RandomState::new());
+    assert_eq!(cache.shard_count(), 4);
+    assert_eq!(cache.capacity(), 8);
+    for i in 0..100 {
+        cache.insert(i, i * 10);
+    }
+    assert!(cache.len() <= 8);
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 0,
+            misses: 0,
+            evictions: 100 - cache.len() as u64,
+        }
+    );
+
+    cache.clear();
+    assert!(cache.is_empty());
+    cache.insert(1, 10);
+    assert_eq!(cache.get(&1), Some(10));
+    assert_eq!(cache.get_with(&1, |v| v + 1), Some(11));
+    assert_eq!(cache.get(&2), None);
+    assert!(cache.contains(&1));
+    assert_eq!(cache.remove(&1), Some((1, 10)));
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (2, 1));
+}
+
+#[test]
+fn test_sharded_lru_set_capacity() {
+    let evicted = Arc::new(Mutex::new(0));
+    let cache = ShardedLru::new(2, 50);
+    {
+        let evicted = Arc::clone(&evicted);
+        cache.set_eviction_listener(move |_, _| *evicted.lock().unwrap() += 1);
+    }
+    for i in 0..100 {
+        cache.insert(i, i);
+    }
+    cache.set_capacity(10);
+    assert!(cache.len() <= 20);
+    assert_eq!(cache.capacity(), 20);
+    cache.shrink_to_fit();
+    assert_eq!(cache.stats().evictions, 100 - cache.len() as u64);
+    assert_eq!(*evicted.lock().unwrap(), 100 - cache.len());
+}
+
+#[test]
+fn test_sharded_lru_listener_reads_cache() {
+    let cache = Arc::new(ShardedLru::new(1, 2));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    {
+        let weak = Arc::downgrade(&cache);
+        let seen = Arc::clone(&seen);
+        cache.set_eviction_listener(move |k, _| {
+            let cache = weak.upgrade().unwrap();
+            seen.lock().unwrap().push((*k, cache.contains(k), cache.len()));
+        });
+    }
+    for i in 0..3 {
+        cache.insert(i, i);
+    }
+    cache.set_capacity(1);
+    assert_eq!(*seen.lock().unwrap(), vec![(0, false, 2), (1, false, 1)]);
+}
+
+#[test]
+fn test_sharded_lru_threads() {
+    let cache = Arc::new(ShardedLru::new(8, 64));
+    let handles = (0..8u64)
+        .map(|t| {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                for i in 0..10_000u64 {
+                    let key = (i * 7 + t) % 1_000;
+                    match cache.get(&key) {
+                        Some(v) => assert_eq!(v, key * 2),
+                        None => {
+                            cache.insert(key, key * 2);
+                        }
+                    }
+                    if i % 97 == 0 {
+                        cache.remove(&key);
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert!(cache.len() <= cache.capacity());
+    let stats = cache.stats();
+    assert_eq!(stats.hits + stats.misses, 80_000);
+}
+
+#[test]
+fn test_concurrent_lru() {
+    let cache = ConcurrentLru::new(3);
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    cache.insert(3, "c");
+    assert_eq!(cache.get(&1), Some("a"));
+    assert_eq!(cache.peek(&2), Some("b"));
+    // The buffered read of 1 is replayed before the insert evicts.
+    assert_eq!(cache.insert(4, "d"), Some((2, "b")));
+    assert_eq!(cache.len(), 3);
+
+    for _ in 0..1_000 {
+        cache.get(&3);
+    }
+    cache.apply_buffered_reads();
+    assert_eq!(cache.insert(5, "e"), Some((1, "a")));
+    assert_eq!(cache.remove(&5), Some((5, "e")));
+    assert!(cache.contains(&3));
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_concurrent_lru_set_capacity() {
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let cache = ConcurrentLru::new(3);
+    {
+        let evicted = Arc::clone(&evicted);
+        cache.set_eviction_listener(move |k, _| evicted.lock().unwrap().push(*k));
+    }
+    cache.insert(1, "a");
+    cache.insert(2, "b");
+    cache.insert(3, "c");
+    cache.get(&1);
+    cache.set_capacity(1);
+    cache.shrink_to_fit();
+    assert_eq!(*evicted.lock().unwrap(), vec![2, 3]);
+    assert_eq!(cache.capacity(), 1);
+}
+
+#[test]
+fn test_concurrent_lru_threads() {
+    let cache = Arc::new(ConcurrentLru::new(100));
+    for i in 0..100 {
+        cache.insert(i, i);
+    }
+    let handles = (0..8)
+        .map(|t| {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                for i in 0..10_000 {
+                    let key = (i * 13 + t) % 150;
+                    match cache.get(&key) {
+                        Some(v) => assert_eq!(v, key),
+                        None if t % 2 == 0 => {
+                            cache.insert(key, key);
+                        }
+                        None => {}
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    cache.apply_buffered_reads();
+    assert_eq!(cache.len(), 100);
+}
+
+#[derive(Default)]
+struct RecordingFlusher {
+    written: Vec<(i32, &'static str)>,
+    fail: bool,
+}
+
+impl Flusher<i32, &'static str> for RecordingFlusher {
+    type Error = i32;
+
+    fn flush(&mut self, key: &i32, value: &&'static str) -> Result<(), i32> {
+        if self.fail {
+            return Err(*key);
+        }
+        self.written.push((*key, *value));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_back() {
+    let mut cache = WriteBackCache::new(2, RecordingFlusher::default());
+    cache.insert_clean(1, "a").unwrap();
+    cache.insert(2, "b").unwrap();
+    assert!(!cache.is_dirty(&1));
+    assert!(cache.is_dirty(&2));
+    assert_eq!(cache.dirty_len(), 1);
+
+    // Evicting a clean entry writes nothing.
+    cache.insert(3, "c").unwrap();
+    assert!(!cache.contains(&1));
+    assert!(cache.flusher().written.is_empty());
+
+    // Evicting a dirty entry writes it first.
+    cache.insert(4, "d").unwrap();
+    assert_eq!(cache.flusher().written, vec![(2, "b")]);
+
+    // A failed write keeps the evicted entry dirty and still caches the new one.
+    cache.flusher_mut().fail = true;
+    assert_eq!(cache.insert(5, "e"), Err(3));
+    assert_eq!(
+        cache.iter().collect::<Vec<_>>(),
+        vec![(&3, &"c", true), (&4, &"d", true), (&5, &"e", true)]
+    );
+    cache.flusher_mut().fail = false;
+
+    // The next insert catches up on the evictions.
+    cache.insert(6, "f").unwrap();
+    assert_eq!(cache.len(), 2);
+    assert_eq!(
+        cache.flusher().written,
+        vec![(2, "b"), (3, "c"), (4, "d")]
+    );
+
+    *cache.get_mut(&5).unwrap() = "E";
+    assert_eq!(cache.flush(&6), Ok(true));
+    assert_eq!(cache.flush(&6), Ok(false));
+    cache.flush_all().unwrap();
+    assert_eq!(
+        cache.flusher().written,
+        vec![(2, "b"), (3, "c"), (4, "d"), (6, "f"), (5, "E")]
+    );
+    assert_eq!(cache.dirty_len(), 0);
+    assert_eq!(cache.get(&6), Some(&"f"));
+    assert_eq!(cache.remove(&6), Some((6, "f")));
+
+    // Nothing fits in a zero capacity, but a value whose write fails is kept.
+    let mut cache = WriteBackCache::new(0, RecordingFlusher::default());
+    cache.insert(1, "a").unwrap();
+    assert!(cache.is_empty());
+    cache.flusher_mut().fail = true;
+    assert_eq!(cache.insert(2, "b"), Err(2));
+    assert_eq!(cache.peek(&2), Some(&"b"));
+    assert_eq!(cache.flusher().written, vec![(1, "a")]);
+}
+
+#[test]
+fn test_write_back_flush_dirty_iter() {
+    let mut cache = WriteBackCache::new(4, RecordingFlusher::default());
+    for (k, v) in [(1, "a"), (2, "b"), (3, "c"), (4, "d")] {
+        cache.insert(k, v).unwrap();
+    }
+    cache.get(&1);
+    cache.insert_clean(2, "B").unwrap();
+
+    assert_eq!(cache.flush_dirty_iter().next(), Some(Ok((&3, &"c"))));
+    assert_eq!(cache.dirty_len(), 2);
+
+    assert_eq!(
+        cache
+            .flush_dirty_iter()
+            .map(|r| r.map(|(k, _)| *k))
+            .collect::<Vec<_>>(),
+        vec![Ok(4), Ok(1)]
+    );
+
+    cache.insert(5, "e").unwrap();
+    cache.set_capacity(1).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(
+        cache.flusher().written,
+        vec![(3, "c"), (4, "d"), (1, "a")]
+    );
+    assert!(cache.is_dirty(&5));
+}
+
+#[test]
+fn test_memory_store() {
+    let mut store = MemoryStore::new();
+    let mut cache = WriteBackCache::new(0, &mut store);
+    cache.insert(1, "a").unwrap();
+    cache.insert_clean(2, "b").unwrap();
+    assert!(cache.is_empty());
+    drop(cache);
+    assert_eq!(store.len(), 1);
+    assert_eq!(store.get(&1), Some(&"a"));
+    assert_eq!(store.into_inner().front(), Some((&1, &"a")));
+}
+
+#[derive(Default)]
+struct CountingLoader {
+    loads: Vec<i32>,
+    batches: usize,
+}
+
+impl Loader<i32, String> for CountingLoader {
+    type Error = String;
+
+    fn load(&mut self, key: &i32) -> Result<String, String> {
+        self.loads.push(*key);
+        if *key < 0 {
+            Err(format!("no row {}", key))
+        } else {
+            Ok(key.to_string())
+        }
+    }
+
+    fn load_all(&mut self, keys: &[&i32]) -> Result<Vec<String>, String> {
+        self.batches += 1;
+        keys.iter().map(|key| self.load(key)).collect()
+    }
+}
+
+#[test]
+fn test_loading_cache() {
+    let mut cache = LoadingCache::new(2, CountingLoader::default());
+    assert_eq!(cache.get(&1), Ok(&"1".to_owned()));
+    assert_eq!(cache.get(&1), Ok(&"1".to_owned()));
+    assert_eq!(cache.get(&-1), Err("no row -1".to_owned()));
+    assert_eq!(cache.get(&-1), Err("no row -1".to_owned()));
+    assert!(!cache.contains(&-1));
+    cache.get(&2).unwrap();
+    cache.get(&3).unwrap();
+    assert!(!cache.contains(&1));
+    assert_eq!(cache.loader().loads, vec![1, -1, -1, 2, 3]);
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 1,
+            misses: 5,
+            evictions: 1,
+        }
+    );
+
+    assert_eq!(cache.invalidate(&3), Some("3".to_owned()));
+    assert_eq!(cache.peek(&2), Some(&"2".to_owned()));
+    cache.invalidate_all();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_loading_cache_negative_caching() {
+    let mut cache = LoadingCache::new(2, CountingLoader::default());
+    cache.set_negative_caching(true);
+    assert!(cache.get(&-1).is_err());
+    assert_eq!(cache.get(&-1), Err("no row -1".to_owned()));
+    assert!(cache.contains(&-1));
+    assert_eq!(cache.peek(&-1), None);
+    assert_eq!(cache.loader().loads, vec![-1]);
+
+    assert_eq!(cache.invalidate(&-1), None);
+    cache.set_negative_caching(false);
+    assert!(cache.get(&-1).is_err());
+    assert!(!cache.contains(&-1));
+}
+
+#[test]
+fn test_loading_cache_get_all() {
+    let mut cache = LoadingCache::new(4, CountingLoader::default());
+    cache.get(&2).unwrap();
+    assert_eq!(
+        cache.get_all(&[1, 2, 3]),
+        Ok(vec!["1".to_owned(), "2".to_owned(), "3".to_owned()])
+    );
+    assert_eq!(cache.loader().batches, 1);
+    assert_eq!(cache.loader().loads, vec![2, 1, 3]);
+    assert!(cache.get_all(&[4, -4]).is_err());
+    assert!(!cache.contains(&4));
+    cache.set_capacity(1);
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "load_all must return one value per key")]
+fn test_loading_cache_get_all_short_batch() {
+    struct ShortLoader;
+
+    impl Loader<i32, i32> for ShortLoader {
+        type Error = ();
+
+        fn load(&mut self, key: &i32) -> Result<i32, ()> {
+            Ok(*key)
+        }
+
+        fn load_all(&mut self, keys: &[&i32]) -> Result<Vec<i32>, ()> {
+            Ok(keys.iter().skip(1).map(|key| **key).collect())
+        }
+    }
+
+    let mut cache = LoadingCache::new(4, ShortLoader);
+    let _ = cache.get_all(&[1, 2]);
+}
+
+#[test]
+fn test_loading_cache_write_through() {
+    let mut store = MemoryStore::new();
+    let mut cache = LoadingCache::with_store(2, |key: &i32| Ok::<_, ()>(key * 10), &mut store);
+    cache.insert(1, 100).unwrap();
+    assert_eq!(cache.get(&1), Ok(&100));
+    assert_eq!(cache.get(&2), Ok(&20));
+    assert_eq!(cache.remove(&1), Ok(Some(100)));
+    assert_eq!(cache.store().get(&1), None);
+    cache.insert(3, 300).unwrap();
+    drop(cache);
+    assert_eq!(store.get(&3), Some(&300));
+    assert_eq!(store.get(&2), None);
+}
+
+#[test]
+fn test_refreshing_cache() {
+    let clock = ManualClock::new();
+    let loads = Arc::new(Mutex::new(0));
+    let loader = {
+        let loads = Arc::clone(&loads);
+        move |key: &u32| {
+            let mut loads = loads.lock().unwrap();
+            *loads += 1;
+            if *key == 0 {
+                Err("no zero")
+            } else {
+                Ok(key * 100 + *loads)
+            }
+        }
+    };
+    let mut cache = RefreshingCache::with_executor_and_clock(
+        10,
+        Duration::from_secs(60),
+        loader,
+        DeferredExecutor::new(),
+        clock.clone(),
+    );
+    cache.set_refresh_after(Duration::from_secs(30));
+
+    assert_eq!(cache.get(&1), Ok(&101));
+    clock.advance(Duration::from_secs(29));
+    assert_eq!(cache.get(&1), Ok(&101));
+    assert_eq!(cache.executor().pending(), 0);
+
+    // Stale: the old value comes back and one reload is queued, however often it is hit.
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(cache.get(&1), Ok(&101));
+    assert_eq!(cache.get(&1), Ok(&101));
+    assert_eq!(cache.pending_refreshes(), 1);
+    assert_eq!(cache.executor().run_pending(), 1);
+    assert_eq!(*loads.lock().unwrap(), 2);
+    assert_eq!(cache.get(&1), Ok(&102));
+    assert_eq!(cache.pending_refreshes(), 0);
+
+    // The reload restarted the expiry clock.
+    clock.advance(Duration::from_secs(45));
+    assert_eq!(cache.get(&1), Ok(&102));
+    cache.executor().run_pending();
+    cache.apply_reloads();
+
+    // Expired entries are loaded synchronously.
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(cache.get(&1), Ok(&104));
+
+    assert_eq!(cache.get(&0), Err("no zero"));
+    cache.insert(2, 7);
+    assert_eq!(cache.get(&2), Ok(&7));
+    assert_eq!(cache.invalidate(&2), Some(7));
+    assert_eq!(cache.get(&2), Ok(&206));
+
+    // A reload scheduled before an invalidate never overwrites the value loaded after it.
+    assert_eq!(cache.get(&3), Ok(&307));
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(cache.get(&3), Ok(&307));
+    assert_eq!(cache.invalidate(&3), Some(307));
+    assert_eq!(cache.pending_refreshes(), 0);
+    assert_eq!(cache.get(&3), Ok(&308));
+    assert_eq!(cache.executor().run_pending(), 1);
+    assert_eq!(cache.get(&3), Ok(&308));
+
+    let mut cache = RefreshingCache::with_hasher(
+        10,
+        Duration::from_secs(60),
+        |key: &u32| Ok::<_, ()>(*key),
+        ImmediateExecutor,
+        clock.clone(),
+        RandomState::new(),
+    );
+    cache.set_refresh_after(Duration::from_secs(30));
+    cache.get(&1).unwrap();
+    clock.advance(Duration::from_secs(30));
+    cache.get(&1).unwrap();
+    assert_eq!(cache.pending_refreshes(), 1);
+    cache.apply_reloads();
+    assert_eq!(cache.pending_refreshes(), 0);
+}
+
+#[cfg(feature = "tiered")]
+#[test]
+fn test_tiered_cache() {
+    use crate::TieredCache;
+
+    let path =
+        std::env::temp_dir().join(format!("linked-hash-map-rs-tiered-{}", std::process::id()));
+    let mut cache = TieredCache::open(&path, 2).unwrap();
+    for i in 0..5 {
+        cache.insert(i, format!("v{}", i)).unwrap();
+    }
+    assert_eq!(cache.memory_len(), 2);
+    assert_eq!(cache.disk_len(), 3);
+    assert_eq!(cache.len(), 5);
+    assert!(cache.contains(&0));
+
+    // A disk hit is promoted and pushes the coldest memory entry out.
+    assert_eq!(cache.get(&0).unwrap(), Some(&"v0".to_string()));
+    assert_eq!(cache.memory_len(), 2);
+    assert_eq!(cache.disk_len(), 4);
+    let bytes = cache.disk_bytes();
+
+    // 0 still has its record, so evicting it again writes nothing.
+    cache.get(&4).unwrap();
+    cache.get(&3).unwrap();
+    assert_eq!(cache.disk_bytes(), bytes);
+    assert_eq!(cache.len(), 5);
+
+    cache.insert(1, "new".to_string()).unwrap();
+    assert_eq!(cache.remove(&2).unwrap(), Some("v2".to_string()));
+    assert_eq!(cache.remove(&2).unwrap(), None);
+    assert!(cache.garbage_bytes() > 0);
+    assert_eq!(cache.get(&9).unwrap(), None);
+
+    cache.compact().unwrap();
+    assert_eq!(cache.garbage_bytes(), 0);
+    assert!(cache.disk_bytes() < bytes);
+    for (key, value) in [(0, "v0"), (1, "new"), (3, "v3"), (4, "v4")] {
+        assert_eq!(cache.get(&key).unwrap(), Some(&value.to_string()));
+    }
+    assert_eq!(cache.len(), 4);
+
+    cache.clear().unwrap();
+    assert!(cache.is_empty());
+    assert_eq!(cache.disk_bytes(), 0);
+    drop(cache);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "persist")]
+#[test]
+fn test_save_and_load() {
+    let mut map = LinkedHashMap::new();
+    map.insert(3, "c".to_string());
+    map.insert(1, "a".to_string());
+    map.insert(2, "b".to_string());
+    let mut bytes = Vec::new();
+    map.save_to(&mut bytes).unwrap();
+    let loaded = LinkedHashMap::<i32, String>::load_from(&bytes[..]).unwrap();
+    assert_eq!(loaded, map);
+    assert!(LruCache::<i32, String>::load_from(&bytes[..]).is_err());
+    assert!(LinkedHashMap::<i32, String>::load_from(&b"nope"[..]).is_err());
+
+    let mut lru = LruCache::with_hasher(3, RandomState::new());
+    lru.insert(1, "a");
+    lru.insert(2, "b");
+    lru.insert(3, "c");
+    lru.pin(&1);
+    lru.get(&2);
+    lru.set_capacity(2);
+    let mut bytes = Vec::new();
+    lru.save_to(&mut bytes).unwrap();
+    let mut loaded = LruCache::<i32, String, RandomState>::load_from(&bytes[..]).unwrap();
+    assert_eq!(loaded.capacity(), 2);
+    assert_eq!(
+        loaded.iter().map(|(k, v)| (*k, v.as_str())).collect::<Vec<_>>(),
+        lru.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+    );
+    assert!(loaded.is_pinned(&1));
+    assert_eq!(loaded.pinned_len(), 1);
+    assert_eq!(loaded.insert(4, "d".to_string()), Some((2, "b".to_string())));
+
+    let clock = ManualClock::new();
+    let mut ttl = TtlCache::with_clock(3, Duration::from_secs(60), clock.clone());
+    ttl.insert(1, 10);
+    clock.advance(Duration::from_secs(50));
+    ttl.insert(2, 20);
+    clock.advance(Duration::from_secs(20));
+    ttl.insert(3, 30);
+    let mut bytes = Vec::new();
+    ttl.save_to(&mut bytes).unwrap();
+    clock.advance(Duration::from_secs(3600));
+    let mut loaded: TtlCache<i32, i32, std::collections::hash_map::RandomState, _> =
+        TtlCache::load_from_with_clock(&bytes[..], clock.clone()).unwrap();
+    assert_eq!(loaded.ttl(), Duration::from_secs(60));
+    assert_eq!(loaded.capacity(), 3);
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded.age(&2), Some(Duration::from_secs(20)));
+    clock.advance(Duration::from_secs(40));
+    assert_eq!(loaded.get(&2), None);
+    assert_eq!(loaded.get(&3), Some(&30));
+
+    let sharded = ShardedLru::new(4, 2);
+    for i in 0..20 {
+        sharded.insert(i, i * 10);
+    }
+    sharded.pin(&19);
+    let mut bytes = Vec::new();
+    sharded.save_to(&mut bytes).unwrap();
+    let loaded = ShardedLru::<i32, i32>::load_from(&bytes[..]).unwrap();
+    assert_eq!(loaded.shard_count(), 4);
+    assert_eq!(loaded.capacity(), 8);
+    assert_eq!(loaded.get(&19), Some(190));
+    // The shard count comes right after the header; a corrupt one is not allocated.
+    bytes[6..14].copy_from_slice(&u64::MAX.to_le_bytes());
+    let err = ShardedLru::<i32, i32>::load_from(&bytes[..]).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let concurrent = ConcurrentLru::new(2);
+    concurrent.insert(1, "a".to_string());
+    concurrent.insert(2, "b".to_string());
+    concurrent.get(&1);
+    let mut bytes = Vec::new();
+    concurrent.save_to(&mut bytes).unwrap();
+    let loaded = ConcurrentLru::<i32, String>::load_from(&bytes[..]).unwrap();
+    assert_eq!(loaded.insert(3, "c".to_string()), Some((2, "b".to_string())));
+
+    let mut cache = WriteBackCache::new(2, MemoryStore::new());
+    cache.insert_clean(1, 10).unwrap();
+    cache.insert(2, 20).unwrap();
+    let mut bytes = Vec::new();
+    cache.save_to(&mut bytes).unwrap();
+    let mut loaded: WriteBackCache<i32, i32, _> =
+        WriteBackCache::load_from(&bytes[..], MemoryStore::new()).unwrap();
+    assert_eq!(loaded.capacity(), 2);
+    assert!(!loaded.is_dirty(&1));
+    assert!(loaded.is_dirty(&2));
+    loaded.flush_all().unwrap();
+    assert_eq!(loaded.flusher().get(&1), None);
+    assert_eq!(loaded.flusher().get(&2), Some(&20));
+}
+
+#[test]
+fn test_codec() {
+    let mut map = LinkedHashMap::new();
+    map.insert("b".to_string(), vec![1u8, 2, 3]);
+    map.insert("a".to_string(), vec![]);
+    map.insert("ü".repeat(100), vec![0xff; 300]);
+    let bytes = map.to_bytes();
+    assert_eq!(&bytes[..5], b"LHMC\x01");
+    let back = LinkedHashMap::<String, Vec<u8>>::from_bytes(&bytes).unwrap();
+    assert_eq!(back, map);
+
+    let mut numbers = LinkedHashMap::new();
+    numbers.insert(-1i64, (1.5f64).to_bits());
+    numbers.insert(i64::MAX, u64::MAX);
+    let back = LinkedHashMap::<i64, u64>::from_bytes(&numbers.to_bytes()).unwrap();
+    assert_eq!(back.iter().collect::<Vec<_>>(), numbers.iter().collect::<Vec<_>>());
+
+    let empty = LinkedHashMap::<char, bool>::new().to_bytes();
+    assert!(LinkedHashMap::<char, bool>::from_bytes(&empty)
+        .unwrap()
+        .is_empty());
+
+    let mut corrupt = bytes.clone();
+    corrupt[7] ^= 1;
+    let err = LinkedHashMap::<String, Vec<u8>>::from_bytes(&corrupt)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(LinkedHashMap::<String, Vec<u8>>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(LinkedHashMap::<String, Vec<u8>>::from_bytes(b"nope").is_err());
+    // Valid framing, but the entries do not decode as the requested types.
+    assert!(LinkedHashMap::<u8, bool>::from_bytes(&map.to_bytes()).is_err());
+
+    // A key length of one spelled with ten bytes, the last one overflowing 64 bits.
+    let snapshot = |len: &[u8]| {
+        let mut bytes = b"LHMC\x01\x01".to_vec();
+        bytes.extend_from_slice(len);
+        bytes.extend_from_slice(b"a\x00");
+        let crc = crate::codec::crc32(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    };
+    let mut len = vec![0x81];
+    len.extend_from_slice(&[0x80; 8]);
+    len.push(0x00);
+    let back = LinkedHashMap::<String, u8>::from_bytes(&snapshot(&len)).unwrap();
+    assert_eq!(back.get("a"), Some(&0));
+    for last in [0x02, 0x81] {
+        *len.last_mut().unwrap() = last;
+        let err = LinkedHashMap::<String, u8>::from_bytes(&snapshot(&len))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn test_durable_linked_hash_map() {
+    use crate::DurableLinkedHashMap;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    let path = std::env::temp_dir().join(format!(
+        "linked-hash-map-rs-test-durable-{}",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    let keys = |map: &DurableLinkedHashMap<u32, String>| {
+        map.iter().map(|(k, _)| *k).collect::<Vec<_>>()
+    };
+
+    let mut map = DurableLinkedHashMap::open(&path).unwrap();
+    for i in 0..5 {
+        map.insert(i, i.to_string()).unwrap();
+    }
+    map.insert(2, "two".to_string()).unwrap();
+    assert_eq!(map.remove(&1).unwrap(), Some((1, "1".to_string())));
+    assert_eq!(map.remove(&1).unwrap(), None);
+    assert!(map.move_to_front(&3).unwrap());
+    assert!(map.move_to_back(&0).unwrap());
+    assert!(!map.move_to_back(&9).unwrap());
+    assert_eq!(map.pop_front().unwrap(), Some((3, "3".to_string())));
+    assert_eq!(map.log_records(), 10);
+    drop(map);
+
+    let mut map = DurableLinkedHashMap::<u32, String>::open(&path).unwrap();
+    assert_eq!(keys(&map), vec![2, 4, 0]);
+    assert_eq!(map.get(&2), Some(&"two".to_string()));
+    assert_eq!(map.log_records(), 10);
+
+    map.compact().unwrap();
+    assert_eq!(map.log_records(), 0);
+    map.insert(7, "7".to_string()).unwrap();
+    drop(map);
+    let map = DurableLinkedHashMap::<u32, String>::open(&path).unwrap();
+    assert_eq!(keys(&map), vec![2, 4, 0, 7]);
+    assert_eq!(map.log_records(), 1);
+    drop(map);
+
+    // A record torn by a crash is dropped, and later appends follow the last good one.
+    let len = fs::metadata(&path).unwrap().len();
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[9, 0, 0, 0, 1, 2])
+        .unwrap();
+    let mut map = DurableLinkedHashMap::<u32, String>::open(&path).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    assert_eq!(keys(&map), vec![2, 4, 0, 7]);
+
+    map.set_compact_after(2);
+    map.clear().unwrap();
+    map.insert(1, "a".to_string()).unwrap();
+    assert_eq!(map.log_records(), 0);
+    map.insert(2, "b".to_string()).unwrap();
+    map.sync().unwrap();
+    drop(map);
+    let map = DurableLinkedHashMap::<u32, String>::open(&path).unwrap();
+    assert_eq!(keys(&map), vec![1, 2]);
+
+    // A failed periodic compaction leaves the mutation applied and the log intact.
+    let tmp = path.with_extension("compact");
+    fs::create_dir(&tmp).unwrap();
+    let mut map = DurableLinkedHashMap::<u32, String>::open(&path).unwrap();
+    map.set_compact_after(0);
+    map.insert(3, "c".to_string()).unwrap();
+    assert!(map.take_compact_error().is_some());
+    assert!(map.take_compact_error().is_none());
+    assert_eq!(map.log_records(), 2);
+    fs::remove_dir(&tmp).unwrap();
+    map.insert(4, "d".to_string()).unwrap();
+    assert!(map.take_compact_error().is_none());
+    assert!(!tmp.exists());
+    drop(map);
+    let map = DurableLinkedHashMap::<u32, String>::open(&path).unwrap();
+    assert_eq!(keys(&map), vec![1, 2, 3, 4]);
+    drop(map);
+
+    // Damage in the middle of the log is not mistaken for a torn tail, whether it hits a
+    // payload or a length.
+    let mut map = DurableLinkedHashMap::<u32, String>::open(&path).unwrap();
+    map.insert(5, "e".to_string()).unwrap();
+    drop(map);
+    let clean = fs::read(&path).unwrap();
+    for &at in &[0, 3, 20] {
+        let mut bytes = clean.clone();
+        bytes[at] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let err = DurableLinkedHashMap::<u32, String>::open(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_observed_linked_hash_map() {
+    use crate::{End, MapEvent, ObservedLinkedHashMap};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut map = ObservedLinkedHashMap::new();
+    map.insert(0, "zero");
+    let sink = events.clone();
+    map.set_observer(move |event| sink.lock().unwrap().push(event.cloned()));
+
+    assert_eq!(map.insert(1, "a"), None);
+    assert_eq!(map.push_front(2, "b"), None);
+    assert_eq!(map.insert(1, "A"), Some("a"));
+    assert!(map.move_to_back(&2));
+    assert!(!map.move_to_front(&9));
+    assert_eq!(map.remove(&9), None);
+    assert_eq!(map.pop_front(), Some((0, "zero")));
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            MapEvent::Inserted { key: 1, value: "a", at: End::Back },
+            MapEvent::Inserted { key: 2, value: "b", at: End::Front },
+            MapEvent::Updated { key: 1, old: "a", new: "A" },
+            MapEvent::Moved { key: 2, from: 0, to: 2 },
+            MapEvent::Removed { key: 0, value: "zero" },
+        ]
+    );
+
+    let mut replica = LinkedHashMap::new();
+    replica.insert(0, "zero");
+    events.lock().unwrap().drain(..).for_each(|event| replica.apply(event));
+    assert_eq!(
+        replica.iter().collect::<Vec<_>>(),
+        map.iter().collect::<Vec<_>>()
+    );
+
+    map.clear();
+    assert_eq!(events.lock().unwrap().pop(), Some(MapEvent::Cleared));
+}
+
+#[test]
+fn test_diff() {
+    use crate::Edit;
+
+    fn map_of<I: IntoIterator<Item = (u64, u64)>>(entries: I) -> LinkedHashMap<u64, u64> {
+        let mut map = LinkedHashMap::new();
+        map.extend(entries);
+        map
+    }
+
+    let old = map_of((1..=6).map(|i| (i, i * 10)));
+    let new = map_of(vec![(2, 20), (7, 70), (1, 10), (3, 30), (5, 55), (4, 40)]);
+    let diff = old.diff(&new);
+    assert_eq!(
+        diff,
+        vec![
+            Edit::Removed { key: 6 },
+            Edit::Moved { key: 2, after: None },
+            Edit::Added { key: 7, value: 70, after: Some(2) },
+            Edit::Moved { key: 5, after: Some(3) },
+            Edit::Changed { key: 5, old: 50, new: 55 },
+        ]
+    );
+    let mut patched = old.clone();
+    patched.apply_diff(diff);
+    assert_eq!(
+        patched.iter().collect::<Vec<_>>(),
+        new.iter().collect::<Vec<_>>()
+    );
+    assert!(new.diff(&new).is_empty());
+
+    // Shuffle, drop and add keys with a small LCG and check every script round-trips.
+    let mut seed = 7u64;
+    let mut next = |n: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % n
+    };
+    for _ in 0..200 {
+        let len = next(12);
+        let old = map_of((0..len).map(|i| (i, next(3))));
+        let mut keys = (0..len + next(4)).filter(|_| next(5) > 0).collect::<Vec<_>>();
+        for i in (1..keys.len()).rev() {
+            keys.swap(i, next(i as u64 + 1) as usize);
+        }
+        let new = map_of(keys.iter().map(|&k| (k, next(3))));
+        let mut patched = old.clone();
+        patched.apply_diff(old.diff(&new));
+        assert_eq!(
+            patched.iter().collect::<Vec<_>>(),
+            new.iter().collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn test_merge_with() {
+    use crate::{MergeOrder, MergeStrategy};
+
+    let map_of = |entries: &[(&'static str, i32)]| {
+        let mut map = LinkedHashMap::new();
+        map.extend(entries.iter().copied());
+        map
+    };
+    let ours = map_of(&[("a", 1), ("b", 2), ("c", 3)]);
+    let theirs = || map_of(&[("x", 10), ("b", 20), ("y", 30), ("z", 40), ("a", 50), ("w", 60)]);
+    let entries = |map: &LinkedHashMap<&'static str, i32>| {
+        map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+    };
+
+    let mut merged = ours.clone();
+    merged.merge_with(theirs(), MergeStrategy::keep_ours());
+    assert_eq!(
+        entries(&merged),
+        vec![("a", 1), ("b", 2), ("c", 3), ("x", 10), ("y", 30), ("z", 40), ("w", 60)]
+    );
+
+    let mut merged = ours.clone();
+    merged.merge_with(
+        theirs(),
+        MergeStrategy::take_theirs().order(MergeOrder::Interleave),
+    );
+    assert_eq!(
+        entries(&merged),
+        vec![("y", 30), ("z", 40), ("a", 50), ("w", 60), ("x", 10), ("b", 20), ("c", 3)]
+    );
+
+    let mut seen = Vec::new();
+    let mut merged = ours.clone();
+    merged.merge_with(
+        theirs(),
+        MergeStrategy::combine(|k, ours: &mut i32, theirs| {
+            seen.push(*k);
+            *ours += theirs;
+        }),
+    );
+    assert_eq!(seen, vec!["b", "a"]);
+    assert_eq!(merged.get("a"), Some(&51));
+    assert_eq!(merged.get("b"), Some(&22));
+
+    let mut merged = ours.clone();
+    merged.merge_with(
+        map_of(&[("p", 1), ("q", 2)]),
+        MergeStrategy::keep_ours().order(MergeOrder::Interleave),
+    );
+    assert_eq!(
+        entries(&merged),
+        vec![("a", 1), ("b", 2), ("c", 3), ("p", 1), ("q", 2)]
+    );
+}
+
+#[test]
+fn test_merge3() {
+    use crate::MergeConflict;
+
+    let map_of = |entries: &[(&'static str, i32)]| {
+        let mut map = LinkedHashMap::new();
+        map.extend(entries.iter().copied());
+        map
+    };
+    let entries = |map: &LinkedHashMap<&'static str, i32>| {
+        map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+    };
+    let base = map_of(&[("a", 1), ("b", 2), ("c", 3), ("d", 4), ("e", 5)]);
+
+    // Non-overlapping edits on each side all land, including a removal, an addition placed
+    // after its neighbour in theirs and a reordering.
+    let ours = map_of(&[("a", 10), ("b", 2), ("d", 4), ("e", 5)]);
+    let theirs = map_of(&[("e", 5), ("a", 1), ("b", 2), ("x", 9), ("c", 3), ("d", 40)]);
+    let merge = LinkedHashMap::merge3(&base, &ours, &theirs);
+    assert!(merge.is_clean());
+    assert_eq!(
+        entries(&merge.merged),
+        vec![("e", 5), ("a", 10), ("b", 2), ("x", 9), ("d", 40)]
+    );
+
+    // The same change on both sides is not a conflict.
+    let same = map_of(&[("b", 2), ("a", 1), ("c", 3), ("d", 4), ("e", 6)]);
+    let merge = LinkedHashMap::merge3(&base, &same, &same);
+    assert!(merge.is_clean());
+    assert_eq!(entries(&merge.merged), entries(&same));
+
+    let ours = map_of(&[("e", 5), ("a", 1), ("b", 2), ("c", 30), ("d", 4)]);
+    let theirs = map_of(&[("a", 1), ("b", 2), ("e", 50), ("d", 4)]);
+    let merge = LinkedHashMap::merge3(&base, &ours, &theirs);
+    assert_eq!(
+        merge.conflicts,
+        vec![
+            MergeConflict::Value { key: "c", base: Some(3), ours: Some(30), theirs: None },
+            MergeConflict::Order { key: "e", ours_after: None, theirs_after: Some("b") },
+        ]
+    );
+    assert_eq!(
+        entries(&merge.merged),
+        vec![("e", 50), ("a", 1), ("b", 2), ("c", 30), ("d", 4)]
+    );
+}
+
+#[test]
+fn test_replicated_linked_hash_map() {
+    use crate::ReplicatedLinkedHashMap;
+
+    type Replica = ReplicatedLinkedHashMap<u64, u64>;
+    let entries = |replica: &Replica| {
+        replica.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+    };
+    let merged = |a: &Replica, b: &Replica| {
+        let mut a = a.clone_for(a.replica());
+        a.merge(b);
+        a
+    };
+
+    let mut seed = 11u64;
+    let mut next = |n: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % n
+    };
+    let mut replicas = vec![Replica::new(1), Replica::new(2), Replica::new(3)];
+    for round in 0..30 {
+        for replica in &mut replicas {
+            for _ in 0..next(4) {
+                let key = next(8);
+                match next(6) {
+                    0 | 1 => replica.insert(key, round),
+                    2 => {
+                        replica.remove(&key);
+                    }
+                    3 => {
+                        replica.move_to_front(&key);
+                    }
+                    4 => {
+                        replica.move_to_back(&key);
+                    }
+                    _ => {
+                        replica.move_after(&key, &next(8));
+                    }
+                }
+            }
+            // Local edits keep the view in step with a full rebuild.
+            assert_eq!(entries(replica), entries(&replica.clone_for(0)));
+        }
+        let (a, b, c) = (&replicas[0], &replicas[1], &replicas[2]);
+        // Commutative, associative and idempotent.
+        assert_eq!(entries(&merged(a, b)), entries(&merged(b, a)));
+        assert_eq!(
+            entries(&merged(&merged(a, b), c)),
+            entries(&merged(a, &merged(b, c)))
+        );
+        assert_eq!(entries(&merged(a, a)), entries(a));
+        if round % 3 == 0 {
+            let all = merged(&merged(a, b), c);
+            for replica in &mut replicas {
+                replica.merge(&all);
+                assert_eq!(entries(replica), entries(&all));
+            }
+        }
+    }
+
+    // Concurrent edits to the same key: the later write wins, and a later remove beats an
+    // earlier update.
+    let mut a = Replica::new(1);
+    a.insert(1, 10);
+    a.insert(2, 20);
+    let mut b = a.clone_for(2);
+    a.insert(1, 11);
+    b.insert(1, 12);
+    b.remove(&2);
+    b.insert(3, 30);
+    assert!(!a.move_after(&1, &3));
+    assert!(a.move_after(&1, &2));
+    a.merge(&b);
+    b.merge(&a);
+    assert_eq!(entries(&a), entries(&b));
+    // Both 1 and 3 were placed right after 2; b's placement is newer, so 3 comes first.
+    assert_eq!(entries(&a), vec![(3, 30), (1, 12)]);
+}
+
+#[test]
+fn test_transaction() {
+    let mut map = LinkedHashMap::new();
+    map.extend(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+    let before = map.clone();
+    let entries = |map: &LinkedHashMap<i32, &'static str>| {
+        map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+    };
+
+    let mut tx = map.begin();
+    assert_eq!(tx.insert(5, "e"), None);
+    assert_eq!(tx.push_front(0, "z"), None);
+    assert_eq!(tx.insert(2, "B"), Some("b"));
+    assert!(tx.move_to_front(&3));
+    assert!(tx.move_to_back(&1));
+    assert!(!tx.move_to_back(&9));
+    assert_eq!(tx.remove(&4), Some((4, "d")));
+    assert_eq!(tx.pop_front(), Some((3, "c")));
+    assert_eq!(tx.pop_back(), Some((1, "a")));
+    *tx.get_mut(&0).unwrap() = "Z";
+    assert_eq!(entries(&tx), vec![(0, "Z"), (2, "B"), (5, "e")]);
+    tx.clear();
+    assert!(tx.is_empty());
+    tx.insert(2, "x");
+    tx.rollback();
+    assert_eq!(entries(&map), entries(&before));
+
+    {
+        let mut tx = map.begin();
+        tx.move_to_back(&2);
+        tx.remove(&3);
+        tx.insert(3, "C");
+    }
+    assert_eq!(entries(&map), entries(&before));
+
+    let mut tx = map.begin();
+    tx.move_to_front(&4);
+    tx.remove(&2);
+    tx.commit();
+    assert_eq!(entries(&map), vec![(4, "d"), (1, "a"), (3, "c")]);
+
+    // Random mutations always roll back to the exact starting order.
+    let mut seed = 7u64;
+    let mut next = |n: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n) as i32
+    };
+    for _ in 0..50 {
+        let before = entries(&map);
+        let mut tx = map.begin();
+        for _ in 0..20 {
+            let key = next(8);
+            match next(7) {
+                0 => drop(tx.insert(key, "i")),
+                1 => drop(tx.push_front(key, "f")),
+                2 => drop(tx.remove(&key)),
+                3 => drop(tx.move_to_front(&key)),
+                4 => drop(tx.move_to_back(&key)),
+                5 => drop(tx.pop_back()),
+                _ => drop(tx.pop_front()),
+            }
+        }
+        drop(tx);
+        assert_eq!(entries(&map), before);
+        map.insert(next(8), "n");
+    }
+}
+
+#[test]
+fn test_history_linked_hash_map() {
+    use crate::HistoryLinkedHashMap;
+
+    let entries = |map: &HistoryLinkedHashMap<i32, &'static str>| {
+        map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+    };
+    let mut map = HistoryLinkedHashMap::new(3);
+    assert!(!map.undo());
+    assert_eq!(map.insert(1, "a"), None);
+    assert_eq!(map.insert(2, "b"), None);
+    map.group(|map| {
+        map.insert(3, "c");
+        map.group(|map| map.insert(1, "A"));
+        assert!(map.move_to_front(&3));
+    });
+    assert_eq!(entries(&map), vec![(3, "c"), (1, "A"), (2, "b")]);
+
+    // Edits that change nothing are not steps.
+    assert!(map.move_to_front(&3));
+    assert!(map.move_to_back(&2));
+    assert!(!map.move_to_back(&9));
+    assert!(!map.move_after(&1, Some(&1)));
+    map.reorder(vec![3, 9]);
+    map.group(|_| ());
+
+    assert!(map.undo());
+    assert_eq!(entries(&map), vec![(1, "a"), (2, "b")]);
+    assert!(map.redo());
+    assert_eq!(entries(&map), vec![(3, "c"), (1, "A"), (2, "b")]);
+    assert!(!map.redo());
+
+    assert!(map.undo());
+    assert!(map.can_redo());
+    assert_eq!(map.remove(&2), Some((2, "b")));
+    assert!(!map.can_redo());
+    assert!(map.undo());
+    assert!(map.undo());
+    assert!(map.undo());
+    assert_eq!(entries(&map), vec![]);
+    assert!(!map.undo());
+    while map.redo() {}
+    assert_eq!(entries(&map), vec![(1, "a")]);
+
+    // Only the latest `limit` steps are kept.
+    map.insert(2, "b");
+    map.insert(3, "c");
+    map.move_to_front(&3);
+    map.sort_by(|(a, _), (b, _)| b.cmp(a));
+    assert_eq!(entries(&map), vec![(3, "c"), (2, "b"), (1, "a")]);
+    assert!(map.undo());
+    assert_eq!(entries(&map), vec![(3, "c"), (1, "a"), (2, "b")]);
+    assert!(map.undo());
+    assert!(map.undo());
+    assert!(!map.undo());
+    assert_eq!(entries(&map), vec![(1, "a"), (2, "b")]);
+    map.clear_history();
+    assert!(!map.can_redo());
+
+    // Undoing every step of random edits walks back through every earlier state in turn, and
+    // redoing walks forward again. Edits that change nothing are not steps, and inserts always
+    // change the value, so each step is one change of state.
+    let mut map = HistoryLinkedHashMap::new(usize::MAX);
+    let mut states = vec![entries(&map)];
+    let mut seed = 11u64;
+    let mut next = |n: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((seed >> 33) % n) as i32
+    };
+    for _ in 0..300 {
+        let (key, other) = (next(10), next(10));
+        match next(7) {
+            0 | 1 => {
+                let value = if map.get(&key) == Some(&"x") { "y" } else { "x" };
+                map.insert(key, value);
+            }
+            2 => {
+                map.remove(&key);
+            }
+            3 => {
+                map.move_to_front(&key);
+            }
+            4 => {
+                map.move_after(&key, Some(&other));
+            }
+            5 => map.sort_by_key(|k, _| (k + other) % 10),
+            _ => map.reorder(vec![other, key]),
+        }
+        if entries(&map) != *states.last().unwrap() {
+            states.push(entries(&map));
+        }
+    }
+    for state in states.iter().rev().skip(1) {
+        assert!(map.undo());
+        assert_eq!(entries(&map), *state);
+    }
+    assert!(!map.undo());
+    for state in states.iter().skip(1) {
+        assert!(map.redo());
+        assert_eq!(entries(&map), *state);
+    }
+    assert!(!map.redo());
+}
+
+#[test]
+fn test_history_group_undo() {
+    use crate::HistoryLinkedHashMap;
+
+    // Undo and redo inside a group would mix a replayed step into the one being recorded.
+    let mut map = HistoryLinkedHashMap::new(10);
+    map.insert("x", 1);
+    map.group(|map| {
+        map.insert("x", 2);
+        assert!(!map.undo());
+        assert!(!map.redo());
+    });
+    assert!(map.undo());
+    assert_eq!(map.get("x"), Some(&1));
+    assert!(map.undo());
+    assert!(map.is_empty());
+    assert!(map.redo());
+    assert!(map.redo());
+    assert_eq!(map.get("x"), Some(&2));
+}
+
+#[test]
+fn test_history_group_panic() {
+    use crate::HistoryLinkedHashMap;
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut map = HistoryLinkedHashMap::new(10);
+    map.insert(1, "a");
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        map.group(|map| {
+            map.insert(2, "b");
+            map.insert(3, "c");
+            panic!("edit failed");
+        })
+    }));
+    assert!(result.is_err());
+
+    // The group was closed: later edits are steps of their own.
+    map.insert(4, "d");
+    assert!(map.undo());
+    assert_eq!(map.len(), 3);
+    assert!(map.undo());
+    assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&1, &"a")]);
+}
+
 #[cfg(all(test, feature = "serde"))]
 mod test_serde {
     use crate::LinkedHashMap;
 
+    #[allow(clippy::redundant_static_lifetimes)]
     const JSON: &'static str = r#"{"1":"a","2":"b"}"#;
 
     #[test]
@@ -170,4 +1627,476 @@ mod test_serde {
         assert_eq!(map.front(), Some((&1i32, &"a".to_owned())));
         assert_eq!(map.back(), Some((&2i32, &"b".to_owned())));
     }
+
+    #[test]
+    fn test_custom_hasher() {
+        use ahash::RandomState;
+        use serde::de::DeserializeSeed;
+
+        use crate::serde::LinkedHashMapSeed;
+
+        let map: LinkedHashMap<i32, String, RandomState> = serde_json::from_str(JSON).unwrap();
+        assert_eq!(serde_json::to_string(&map).unwrap(), JSON);
+
+        let hasher = RandomState::with_seeds(1, 2, 3, 4);
+        let mut de = serde_json::Deserializer::from_str(JSON);
+        let map = LinkedHashMapSeed::<i32, String, _>::new(hasher)
+            .deserialize(&mut de)
+            .unwrap();
+        assert_eq!(map.front(), Some((&1, &"a".to_owned())));
+        assert_eq!(map.back(), Some((&2, &"b".to_owned())));
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Pairs {
+        #[serde(with = "crate::serde::as_seq")]
+        map: LinkedHashMap<u32, String>,
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Entries {
+        #[serde(with = "crate::serde::as_seq::entries")]
+        map: LinkedHashMap<u32, String>,
+    }
+
+    #[test]
+    fn test_as_seq() {
+        let mut map = LinkedHashMap::new();
+        map.insert(10, "a".to_owned());
+        map.insert(2, "b".to_owned());
+
+        let pairs = Pairs { map: map.clone() };
+        let json = serde_json::to_string(&pairs).unwrap();
+        assert_eq!(json, r#"{"map":[[10,"a"],[2,"b"]]}"#);
+        assert_eq!(serde_json::from_str::<Pairs>(&json).unwrap(), pairs);
+
+        let entries = Entries { map };
+        let json = serde_json::to_string(&entries).unwrap();
+        assert_eq!(
+            json,
+            r#"{"map":[{"key":10,"value":"a"},{"key":2,"value":"b"}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Entries>(&json).unwrap(), entries);
+
+        // Every form is accepted whichever one was used to write.
+        let from_pairs: Entries = serde_json::from_str(r#"{"map":[[10,"a"],[2,"b"]]}"#).unwrap();
+        assert_eq!(from_pairs, entries);
+        let from_map: Pairs = serde_json::from_str(r#"{"map":{"10":"a","2":"b"}}"#).unwrap();
+        assert_eq!(from_map.map, entries.map);
+
+        assert!(serde_json::from_str::<Pairs>(r#"{"map":[[1]]}"#).is_err());
+        assert!(serde_json::from_str::<Pairs>(r#"{"map":[[1,"a","b"]]}"#).is_err());
+        assert!(serde_json::from_str::<Pairs>(r#"{"map":[{"key":1}]}"#).is_err());
+        assert!(serde_json::from_str::<Pairs>(r#"{"map":[{"key":1,"val":"a"}]}"#).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_as_seq_bincode() {
+        let mut map = LinkedHashMap::new();
+        map.insert(10, "a".to_owned());
+        map.insert(2, "b".to_owned());
+        let pairs = Pairs { map };
+        let bytes = bincode::serialize(&pairs).unwrap();
+        assert_eq!(bincode::deserialize::<Pairs>(&bytes).unwrap(), pairs);
+        let entries: Entries = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(entries.map, pairs.map);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        use serde::de::DeserializeSeed;
+        use std::collections::hash_map::RandomState;
+
+        use crate::serde::{DuplicateKeys, LinkedHashMapSeed, UniqueKeys};
+
+        const DUPLICATED: &str = r#"{"a":1,"b":2,"a":3}"#;
+        let load = |policy| {
+            let mut de = serde_json::Deserializer::from_str(DUPLICATED);
+            LinkedHashMapSeed::<String, i32, _>::new(RandomState::new())
+                .duplicate_keys(policy)
+                .deserialize(&mut de)
+                .map(|map| {
+                    map.iter()
+                        .map(|(k, v)| (k.clone(), *v))
+                        .collect::<Vec<_>>()
+                })
+        };
+        let entries = |pairs: &[(&str, i32)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect::<Vec<_>>()
+        };
+
+        let err = load(DuplicateKeys::Error).unwrap_err();
+        assert!(err.to_string().starts_with("duplicate key"), "{}", err);
+        let mut de = serde_json::Deserializer::from_str(DUPLICATED);
+        let err = LinkedHashMapSeed::<String, i32, _>::new(RandomState::new())
+            .reject_duplicate_keys()
+            .deserialize(&mut de)
+            .unwrap_err();
+        assert!(err.to_string().contains(r#"duplicate key "a""#), "{}", err);
+        assert_eq!(
+            load(DuplicateKeys::KeepFirst).unwrap(),
+            entries(&[("a", 1), ("b", 2)])
+        );
+        assert_eq!(
+            load(DuplicateKeys::LastAtFirstPosition).unwrap(),
+            entries(&[("a", 3), ("b", 2)])
+        );
+        assert_eq!(
+            load(DuplicateKeys::LastAtLastPosition).unwrap(),
+            entries(&[("b", 2), ("a", 3)])
+        );
+
+        // Plain deserialization keeps its behavior.
+        let map: LinkedHashMap<String, i32> = serde_json::from_str(DUPLICATED).unwrap();
+        assert_eq!(map.get("a"), Some(&3));
+        assert_eq!(map.front(), Some((&"a".to_owned(), &3)));
+
+        // Policies other than rejecting by name need no Debug keys.
+        #[derive(serde::Deserialize, PartialEq, Eq, Hash)]
+        struct Key(String);
+        let mut de = serde_json::Deserializer::from_str(DUPLICATED);
+        let map = LinkedHashMapSeed::<Key, i32, _>::new(RandomState::new())
+            .duplicate_keys(DuplicateKeys::KeepFirst)
+            .deserialize(&mut de)
+            .unwrap();
+        assert_eq!(map.get(&Key("a".to_string())), Some(&1));
+        let mut de = serde_json::Deserializer::from_str(DUPLICATED);
+        assert!(LinkedHashMapSeed::<Key, i32, _>::new(RandomState::new())
+            .duplicate_keys(DuplicateKeys::Error)
+            .deserialize(&mut de)
+            .is_err());
+
+        assert!(serde_json::from_str::<UniqueKeys<String, i32>>(DUPLICATED).is_err());
+        let unique: UniqueKeys<String, i32> = serde_json::from_str(r#"{"a":1}"#).unwrap();
+        assert_eq!(serde_json::to_string(&unique).unwrap(), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn test_max_entries() {
+        use serde::de::DeserializeSeed;
+        use std::collections::hash_map::RandomState;
+
+        use crate::serde::LinkedHashMapSeed;
+
+        let load = |json: &str, max| {
+            let mut de = serde_json::Deserializer::from_str(json);
+            LinkedHashMapSeed::<String, i32, _>::new(RandomState::new())
+                .max_entries(max)
+                .deserialize(&mut de)
+        };
+        assert_eq!(load(r#"{"a":1,"b":2}"#, 2).unwrap().len(), 2);
+        let err = load(r#"{"a":1,"b":2,"c":3}"#, 2).err().unwrap();
+        assert!(err.to_string().contains("more than 2 entries"), "{}", err);
+        // Repeated keys count too.
+        assert!(load(r#"{"a":1,"a":2,"a":3}"#, 2).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_max_entries_bincode() {
+        use bincode::Options;
+        use std::collections::hash_map::RandomState;
+
+        use crate::serde::LinkedHashMapSeed;
+
+        let options = || bincode::options().with_fixint_encoding();
+        // A length prefix claiming far more entries than the payload holds.
+        let forged = u64::MAX.to_le_bytes();
+        let seed = || LinkedHashMapSeed::<u64, u64, _>::new(RandomState::new());
+        let err = options()
+            .deserialize_seed(seed().max_entries(100), &forged)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("more than 100 entries"), "{}", err);
+        // Without a limit the bogus hint is not trusted for the allocation either; the input
+        // just runs out.
+        assert!(options().deserialize_seed(seed(), &forged).is_err());
+
+        let mut map = LinkedHashMap::new();
+        map.insert(1u64, 2u64);
+        let bytes = options().serialize(&map).unwrap();
+        let loaded = options()
+            .deserialize_seed(seed().max_entries(1), &bytes)
+            .unwrap();
+        assert_eq!(loaded.get(&1), Some(&2));
+    }
+
+    #[test]
+    fn test_lru_cache_seed() {
+        use serde::de::DeserializeSeed;
+
+        use crate::serde::LruCacheSeed;
+
+        let entries = (0..1000)
+            .map(|i| format!(r#""{}":{}"#, i, i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let json = format!("{{{}}}", entries);
+        let mut de = serde_json::Deserializer::from_str(&json);
+        let mut cache = LruCacheSeed::<String, i32>::new(3)
+            .deserialize(&mut de)
+            .unwrap();
+        assert_eq!(cache.capacity(), 3);
+        assert_eq!(
+            cache.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            vec![997, 998, 999]
+        );
+        assert_eq!(cache.get("0"), None);
+
+        let mut de = serde_json::Deserializer::from_str(r#"[[1,"a"],[2,"b"],[1,"c"]]"#);
+        let cache = LruCacheSeed::<i32, String>::new(2)
+            .deserialize(&mut de)
+            .unwrap();
+        assert_eq!(
+            cache.iter().map(|(k, v)| (*k, v.as_str())).collect::<Vec<_>>(),
+            vec![(2, "b"), (1, "c")]
+        );
+    }
+
+    #[test]
+    fn test_lru_cache_serde() {
+        use crate::LruCache;
+
+        let mut cache = LruCache::new(3);
+        cache.insert(1, "a".to_string());
+        cache.insert(2, "b".to_string());
+        cache.insert(3, "c".to_string());
+        cache.get(&1);
+        cache.pin(&2);
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_eq!(
+            json,
+            r#"{"capacity":3,"entries":[[2,"b",true],[3,"c",false],[1,"a",false]]}"#
+        );
+        let mut back: LruCache<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.capacity(), 3);
+        assert!(back.is_pinned(&2));
+        back.insert(4, "d".to_string());
+        assert_eq!(back.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![2, 1, 4]);
+
+        let json = r#"{"entries":[[1,1,true],[2,2,false],[3,3,false]],"capacity":2}"#;
+        let back: LruCache<i32, i32> = serde_json::from_str(json).unwrap();
+        assert_eq!(back.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(serde_json::from_str::<LruCache<i32, i32>>(r#"{"capacity":2}"#).is_err());
+        let json = r#"{"capacity":2,"ttl":1,"entries":[]}"#;
+        assert!(serde_json::from_str::<LruCache<i32, i32>>(json).is_err());
+
+        #[cfg(feature = "bincode")]
+        {
+            let bytes = bincode::serialize(&cache).unwrap();
+            let back: LruCache<i32, String> = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(back.iter().collect::<Vec<_>>(), cache.iter().collect::<Vec<_>>());
+            assert!(back.is_pinned(&2));
+        }
+    }
+
+    #[test]
+    fn test_cache_serde_field_ids() {
+        use serde::de::value::MapDeserializer;
+        use serde::Deserialize;
+        use serde_json::{json, Value};
+
+        use crate::{LruCache, ShardedLru};
+
+        // Formats that send field identifiers as indexes or bytes instead of strings.
+        let fields = vec![(1u64, json!([[1, 1, false], [2, 2, true]])), (0, json!(2))];
+        let map = MapDeserializer::<_, serde_json::Error>::new(fields.into_iter());
+        let back = LruCache::<i32, i32>::deserialize(map).unwrap();
+        assert_eq!(back.capacity(), 2);
+        assert!(back.is_pinned(&2));
+
+        let fields = vec![(0u64, json!(1)), (1, json!(2)), (2, json!([[1, 1, false]]))];
+        let map = MapDeserializer::<_, serde_json::Error>::new(fields.into_iter());
+        let back = ShardedLru::<i32, i32>::deserialize(map).unwrap();
+        assert_eq!((back.shard_count(), back.capacity()), (1, 2));
+
+        let fields = vec![(2u64, json!(1)), (0, json!(2))];
+        let map = MapDeserializer::<_, serde_json::Error>::new(fields.into_iter());
+        assert!(LruCache::<i32, i32>::deserialize(map).is_err());
+
+        let fields: Vec<(&[u8], Value)> = vec![(b"capacity", json!(2)), (b"entries", json!([]))];
+        let map = MapDeserializer::<_, serde_json::Error>::new(fields.into_iter());
+        assert_eq!(LruCache::<i32, i32>::deserialize(map).unwrap().capacity(), 2);
+        let fields: Vec<(&[u8], Value)> = vec![(b"ttl", json!(2)), (b"entries", json!([]))];
+        let map = MapDeserializer::<_, serde_json::Error>::new(fields.into_iter());
+        assert!(LruCache::<i32, i32>::deserialize(map).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_lru_serde() {
+        use crate::ConcurrentLru;
+
+        let concurrent = ConcurrentLru::new(2);
+        concurrent.insert(1, 10);
+        concurrent.insert(2, 20);
+        concurrent.get(&1);
+        concurrent.pin(&2);
+        let json = serde_json::to_string(&concurrent).unwrap();
+        assert_eq!(json, r#"{"capacity":2,"entries":[[2,20,true],[1,10,false]]}"#);
+        let back: ConcurrentLru<i32, i32> = serde_json::from_str(&json).unwrap();
+        back.insert(3, 30);
+        assert_eq!(back.peek(&2), Some(20));
+        assert_eq!(back.peek(&1), None);
+    }
+
+    #[test]
+    fn test_sharded_lru_serde() {
+        use crate::ShardedLru;
+
+        let sharded = ShardedLru::new(4, 6);
+        for i in 0..6 {
+            sharded.insert(i, i * 10);
+        }
+        sharded.pin(&0);
+        let json = serde_json::to_string(&sharded).unwrap();
+        assert!(json.starts_with(r#"{"shards":4,"capacity":6,"entries":[["#));
+        let back: ShardedLru<i32, i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!((back.shard_count(), back.capacity()), (4, 24));
+        assert_eq!(back.len(), 6);
+        assert_eq!(back.stats(), Default::default());
+        for i in 0..6 {
+            assert_eq!(back.get(&i), Some(i * 10));
+        }
+        assert!(back.unpin(&0));
+
+        let json = r#"{"shards":1,"capacity":2,"entries":[[1,1,false],[2,2,true],[3,3,false]]}"#;
+        let back: ShardedLru<i32, i32> = serde_json::from_str(json).unwrap();
+        assert_eq!((back.get(&1), back.get(&2), back.get(&3)), (None, Some(2), Some(3)));
+        let json = r#"{"shards":0,"capacity":2,"entries":[]}"#;
+        assert!(serde_json::from_str::<ShardedLru<i32, i32>>(json).is_err());
+        let json = r#"{"shards":100000,"capacity":2,"entries":[]}"#;
+        assert!(serde_json::from_str::<ShardedLru<i32, i32>>(json).is_err());
+    }
+
+    #[test]
+    fn test_write_back_cache_serde() {
+        use serde::de::DeserializeSeed;
+
+        use crate::serde::WriteBackCacheSeed;
+        use crate::{MemoryStore, WriteBackCache};
+
+        let mut write_back = WriteBackCache::new(2, MemoryStore::new());
+        write_back.insert(1, "a".to_string()).unwrap();
+        write_back.insert_clean(2, "b".to_string()).unwrap();
+        write_back.get(&1);
+        let json = serde_json::to_string(&write_back).unwrap();
+        assert_eq!(json, r#"{"capacity":2,"entries":[[2,"b",false],[1,"a",true]]}"#);
+        let mut back: WriteBackCache<i32, String, MemoryStore<i32, String>> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(back.capacity(), 2);
+        assert_eq!(back.iter().collect::<Vec<_>>(), write_back.iter().collect::<Vec<_>>());
+        back.flush_all().unwrap();
+        assert_eq!(back.flusher().get(&1), Some(&"a".to_string()));
+        assert_eq!(back.flusher().len(), 1);
+
+        let mut store = MemoryStore::new();
+        let json = r#"{"capacity":1,"entries":[[1,"a",true],[2,"b",true]]}"#;
+        let mut json = serde_json::Deserializer::from_str(json);
+        let back = WriteBackCacheSeed::new(&mut store).deserialize(&mut json).unwrap();
+        assert_eq!(back.iter().collect::<Vec<_>>(), vec![(&2, &"b".to_string(), true)]);
+        drop(back);
+        assert_eq!(store.get(&1), Some(&"a".to_string()));
+    }
+
+    #[test]
+    fn test_ttl_cache_serde() {
+        use std::collections::hash_map::RandomState;
+        use std::time::Duration;
+
+        use crate::{ManualClock, TtlCache};
+
+        let clock = ManualClock::new();
+        let mut ttl = TtlCache::with_clock(3, Duration::from_secs(60), clock.clone());
+        ttl.insert("old", 1);
+        clock.advance(Duration::from_secs(40));
+        ttl.insert("new", 2);
+        clock.advance(Duration::from_secs(30));
+        ttl.insert("newest", 3);
+        let json = serde_json::to_string(&ttl).unwrap();
+        let mut back: TtlCache<&str, i32, RandomState, ManualClock> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(back.ttl(), Duration::from_secs(60));
+        assert_eq!(back.len(), 2);
+        assert_eq!(back.age("new"), Some(Duration::from_secs(30)));
+        back.clock().advance(Duration::from_secs(30));
+        assert_eq!(back.get("new"), None);
+        assert_eq!(back.get("newest"), Some(&3));
+
+        #[cfg(feature = "bincode")]
+        {
+            let bytes = bincode::serialize(&back).unwrap();
+            let back: TtlCache<&str, i32> = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(back.len(), 1);
+            assert!(back.age("newest").unwrap() >= Duration::from_secs(30));
+        }
+    }
+}
+
+#[cfg(loom)]
+mod loom_access_buffer {
+    use crate::buffer::AccessBuffer;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn test_concurrent_push() {
+        loom::model(|| {
+            let buffer = Arc::new(AccessBuffer::new(2));
+            let handles = (0..2)
+                .map(|i| {
+                    let buffer = Arc::clone(&buffer);
+                    thread::spawn(move || buffer.push(i).is_ok())
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                assert!(handle.join().unwrap());
+            }
+            assert_eq!(buffer.push(2), Err(2));
+            let mut drained = vec![buffer.pop().unwrap(), buffer.pop().unwrap()];
+            drained.sort_unstable();
+            assert_eq!(drained, vec![0, 1]);
+            assert_eq!(buffer.pop(), None);
+        });
+    }
+
+    #[test]
+    fn test_pop_while_pushing() {
+        loom::model(|| {
+            let buffer = Arc::new(AccessBuffer::new(2));
+            let producer = {
+                let buffer = Arc::clone(&buffer);
+                thread::spawn(move || {
+                    buffer.push(1).unwrap();
+                    buffer.push(2).unwrap();
+                })
+            };
+            let mut drained = Vec::new();
+            drained.extend(buffer.pop());
+            producer.join().unwrap();
+            while let Some(value) = buffer.pop() {
+                drained.push(value);
+            }
+            assert_eq!(drained, vec![1, 2]);
+        });
+    }
+
+    #[test]
+    fn test_drop_releases_items() {
+        loom::model(|| {
+            let item = Arc::new(());
+            let buffer = Arc::new(AccessBuffer::new(2));
+            let producer = {
+                let buffer = Arc::clone(&buffer);
+                let item = Arc::clone(&item);
+                thread::spawn(move || buffer.push(item).is_ok())
+            };
+            assert!(producer.join().unwrap());
+            drop(buffer);
+            assert_eq!(Arc::strong_count(&item), 1);
+        });
+    }
 }
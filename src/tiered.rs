@@ -0,0 +1,288 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::LruCache;
+
+/// Where a spilled value lives in the segment file.
+#[derive(Clone, Copy)]
+struct Location {
+    offset: u64,
+    len: u64,
+}
+
+struct Slot<V> {
+    value: V,
+    /// The value also has an up-to-date record on disk, so evicting it writes nothing.
+    on_disk: bool,
+}
+
+/// A two-tier cache: an in-memory [LruCache] backed by an append-only segment file.
+///
+/// Entries evicted from the memory tier are serialized with bincode and appended to the
+/// segment, and an in-memory index maps their keys to the records. A miss in memory that hits
+/// the index reads the value back and promotes it, so the memory tier always holds the hot set
+/// while the disk tier holds everything else.
+///
+/// Records are never rewritten in place: overwriting or removing a spilled key leaves its old
+/// record behind as garbage until [compact](TieredCache::compact) is called. The segment is
+/// scratch space, truncated when the cache is opened and not meant to be read by anything else.
+///
+/// ```rust
+/// use linked_hash_map_rs::TieredCache;
+///
+/// let path = std::env::temp_dir().join("linked-hash-map-rs-doc-tiered");
+/// let mut cache = TieredCache::open(&path, 1).unwrap();
+/// cache.insert(1, "a".to_string()).unwrap();
+/// cache.insert(2, "b".to_string()).unwrap();
+/// assert_eq!(cache.disk_len(), 1);
+/// assert_eq!(cache.get(&1).unwrap(), Some(&"a".to_string()));
+/// # drop(cache);
+/// # std::fs::remove_file(path).unwrap();
+/// ```
+pub struct TieredCache<K, V, S = RandomState> {
+    memory: LruCache<K, Slot<V>, S>,
+    index: HashMap<K, Location, S>,
+    path: PathBuf,
+    file: File,
+    end: u64,
+    garbage: u64,
+}
+
+impl<K, V> TieredCache<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Serialize + DeserializeOwned,
+{
+    /// Creates a cache holding `capacity` entries in memory and spilling the rest to the
+    /// segment file at `path`, which is created or truncated.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Self> {
+        TieredCache::with_hasher(path, capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> TieredCache<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Serialize + DeserializeOwned,
+    S: BuildHasher + Clone,
+{
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero: a value read from disk is returned from the memory tier.
+    pub fn with_hasher<P: AsRef<Path>>(path: P, capacity: usize, hasher: S) -> io::Result<Self> {
+        assert!(capacity > 0, "TieredCache needs a capacity of at least one");
+        let path = path.as_ref().to_path_buf();
+        Ok(TieredCache {
+            memory: LruCache::with_hasher(capacity, hasher.clone()),
+            index: HashMap::with_hasher(hasher),
+            file: create(&path)?,
+            path,
+            end: 0,
+            garbage: 0,
+        })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Capacity of the memory tier. The disk tier is unbounded.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.memory.capacity()
+    }
+
+    /// Number of distinct keys across both tiers.
+    pub fn len(&self) -> usize {
+        self.memory.len() + self.index.len()
+            - self.memory.iter().filter(|(_, slot)| slot.on_disk).count()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty() && self.index.is_empty()
+    }
+
+    /// Number of entries held in memory.
+    #[inline]
+    pub fn memory_len(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Number of entries with a live record on disk, including ones also held in memory.
+    #[inline]
+    pub fn disk_len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Size of the segment file in bytes.
+    #[inline]
+    pub fn disk_bytes(&self) -> u64 {
+        self.end
+    }
+
+    /// Bytes of the segment file taken by records that are no longer reachable.
+    #[inline]
+    pub fn garbage_bytes(&self) -> u64 {
+        self.garbage
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.memory.contains(key) || self.index.contains_key(key)
+    }
+
+    /// Returns the value for `key`, reading it back from disk and promoting it to the memory
+    /// tier if it was spilled.
+    pub fn get<Q>(&mut self, key: &Q) -> io::Result<Option<&V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.memory.contains(key) {
+            let (key, location) = match self.index.get_key_value(key) {
+                Some((key, location)) => (key.clone(), *location),
+                None => return Ok(None),
+            };
+            let value = self.read(location)?;
+            self.admit(
+                key,
+                Slot {
+                    value,
+                    on_disk: true,
+                },
+            )?;
+        }
+        Ok(self.memory.get(key).map(|slot| &slot.value))
+    }
+
+    /// Inserts `value` into the memory tier, spilling the least recently used entry if it is
+    /// full. A record already on disk for `key` becomes garbage.
+    ///
+    /// If writing the spilled entry fails it is dropped, and the error returned.
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<()> {
+        self.discard(&key);
+        self.admit(
+            key,
+            Slot {
+                value,
+                on_disk: false,
+            },
+        )
+    }
+
+    /// Removes `key` from both tiers, returning its value if it was cached.
+    pub fn remove<Q>(&mut self, key: &Q) -> io::Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let in_memory = self.memory.remove(key).map(|(_, slot)| slot.value);
+        let on_disk = match self.index.get(key) {
+            Some(&location) if in_memory.is_none() => Some(self.read(location)?),
+            _ => None,
+        };
+        self.discard(key);
+        Ok(in_memory.or(on_disk))
+    }
+
+    /// Empties both tiers and truncates the segment file.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.memory.clear();
+        self.index.clear();
+        self.file.set_len(0)?;
+        self.end = 0;
+        self.garbage = 0;
+        Ok(())
+    }
+
+    /// Rewrites the segment file with only the live records, reclaiming the garbage.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let tmp = self.path.with_extension("compact");
+        let mut out = create(&tmp)?;
+        let mut offsets = Vec::with_capacity(self.index.len());
+        let mut end = 0;
+        let mut buf = Vec::new();
+        for location in self.index.values() {
+            buf.resize(location.len as usize, 0);
+            self.file.seek(SeekFrom::Start(location.offset))?;
+            self.file.read_exact(&mut buf)?;
+            out.write_all(&buf)?;
+            offsets.push(end);
+            end += location.len;
+        }
+        out.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        for (location, offset) in self.index.values_mut().zip(offsets) {
+            location.offset = offset;
+        }
+        self.file = out;
+        self.end = end;
+        self.garbage = 0;
+        Ok(())
+    }
+
+    fn admit(&mut self, key: K, slot: Slot<V>) -> io::Result<()> {
+        if let Some((key, slot)) = self.memory.insert(key, slot) {
+            if !slot.on_disk {
+                self.spill(key, &slot.value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self, key: K, value: &V) -> io::Result<()> {
+        let bytes = bincode::serialize(value).map_err(invalid_data)?;
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&bytes)?;
+        let location = Location {
+            offset: self.end,
+            len: bytes.len() as u64,
+        };
+        self.end += location.len;
+        self.index.insert(key, location);
+        Ok(())
+    }
+
+    fn read(&mut self, location: Location) -> io::Result<V> {
+        let mut bytes = vec![0; location.len as usize];
+        self.file.seek(SeekFrom::Start(location.offset))?;
+        self.file.read_exact(&mut bytes)?;
+        bincode::deserialize(&bytes).map_err(invalid_data)
+    }
+
+    fn discard<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(location) = self.index.remove(key) {
+            self.garbage += location.len;
+        }
+    }
+}
+
+fn create(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+fn invalid_data(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,268 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+use std::ops::Deref;
+
+use crate::LinkedHashMap;
+
+/// The inverse of one mutation made through a [Transaction].
+enum Undo<K, V> {
+    /// A new key was added.
+    Remove(K),
+    /// An existing key's value was replaced.
+    Restore(K, V),
+    /// A key was removed from right after `after`, or from the front.
+    Reinsert { key: K, value: V, after: Option<K> },
+    /// A key was moved from right after `after`, or from the front.
+    Move { key: K, after: Option<K> },
+    /// The map was cleared of these entries, in order.
+    Refill(Vec<(K, V)>),
+}
+
+impl<K, V, S> LinkedHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Starts a transaction: mutations made through the returned guard are undone, contents and
+    /// order alike, unless it is [committed](Transaction::commit).
+    ///
+    /// ```rust
+    /// use linked_hash_map_rs::LinkedHashMap;
+    ///
+    /// let mut routes = LinkedHashMap::new();
+    /// routes.extend(vec![("/", "index"), ("/about", "about")]);
+    ///
+    /// let mut tx = routes.begin();
+    /// tx.move_to_back(&"/");
+    /// tx.insert("/admin", "admin");
+    /// tx.remove(&"/about");
+    /// // Validation failed midway; dropping the guard would do the same.
+    /// tx.rollback();
+    /// assert_eq!(
+    ///     routes.iter().collect::<Vec<_>>(),
+    ///     vec![(&"/", &"index"), (&"/about", &"about")]
+    /// );
+    ///
+    /// let mut tx = routes.begin();
+    /// tx.insert("/admin", "admin");
+    /// tx.commit();
+    /// assert_eq!(routes.len(), 3);
+    /// ```
+    pub fn begin(&mut self) -> Transaction<'_, K, V, S> {
+        Transaction {
+            map: self,
+            undo: Vec::new(),
+        }
+    }
+}
+
+/// A guard from [begin](LinkedHashMap::begin) that logs how to undo every mutation made through
+/// it, and undoes them all on drop unless [committed](Transaction::commit).
+///
+/// The map can be read through the guard. Mutations keep a copy of every key and value they
+/// overwrite or remove, and rolling back takes time linear in the number of mutations made plus
+/// the number of entries [clear](Transaction::clear) removed.
+pub struct Transaction<'a, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    map: &'a mut LinkedHashMap<K, V, S>,
+    undo: Vec<Undo<K, V>>,
+}
+
+impl<K, V, S> Deref for Transaction<'_, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Target = LinkedHashMap<K, V, S>;
+
+    fn deref(&self) -> &Self::Target {
+        self.map
+    }
+}
+
+impl<K, V, S> Transaction<'_, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Keeps every mutation made so far.
+    pub fn commit(mut self) {
+        self.undo.clear();
+    }
+
+    /// Undoes every mutation made so far, restoring the contents and order the map had when the
+    /// transaction began.
+    pub fn rollback(self) {}
+
+    fn undo_all(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::Remove(key) => {
+                    self.map.remove(&key);
+                }
+                Undo::Restore(key, value) => {
+                    if let Some(slot) = self.map.get_mut(&key) {
+                        *slot = value;
+                    }
+                }
+                Undo::Reinsert { key, value, after } => {
+                    self.map.push_after(key, value, after.as_ref());
+                }
+                Undo::Move { key, after } => {
+                    self.map.move_after(&key, after.as_ref());
+                }
+                Undo::Refill(entries) => {
+                    for (key, value) in entries {
+                        self.map.push_back(key, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, S> Transaction<'_, K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Same as [push_back](Transaction::push_back).
+    #[inline]
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.push_back(key, value)
+    }
+
+    /// Pushes a new key to the front, or updates an existing one in place, returning the old
+    /// value.
+    pub fn push_front(&mut self, key: K, value: V) -> Option<V> {
+        if self.map.contains(&key) {
+            return self.replace(key, value);
+        }
+        self.undo.push(Undo::Remove(key.clone()));
+        self.map.push_front(key, value);
+        None
+    }
+
+    /// Pushes a new key to the back, or updates an existing one in place, returning the old
+    /// value.
+    pub fn push_back(&mut self, key: K, value: V) -> Option<V> {
+        if self.map.contains(&key) {
+            return self.replace(key, value);
+        }
+        self.undo.push(Undo::Remove(key.clone()));
+        self.map.push_back(key, value);
+        None
+    }
+
+    /// Returns the value for `key` to change in place, logging a copy of it first.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (stored, value) = self.map.get_key_value(key)?;
+        self.undo.push(Undo::Restore(stored.clone(), value.clone()));
+        self.map.get_mut(key)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let after = self.map.key_before(key).cloned();
+        let (key, value) = self.map.remove(key)?;
+        self.undo.push(Undo::Reinsert {
+            key: key.clone(),
+            value: value.clone(),
+            after,
+        });
+        Some((key, value))
+    }
+
+    pub fn pop_front(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.map.pop_front()?;
+        self.undo.push(Undo::Reinsert {
+            key: key.clone(),
+            value: value.clone(),
+            after: None,
+        });
+        Some((key, value))
+    }
+
+    pub fn pop_back(&mut self) -> Option<(K, V)> {
+        let key = self.map.back()?.0.clone();
+        self.remove(&key)
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.log_move(key) {
+            return false;
+        }
+        self.map.move_to_front(key);
+        true
+    }
+
+    /// Returns whether `key` was found and moved.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if !self.log_move(key) {
+            return false;
+        }
+        self.map.move_to_back(key);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        let mut entries = Vec::with_capacity(self.map.len());
+        while let Some(entry) = self.map.pop_front() {
+            entries.push(entry);
+        }
+        self.undo.push(Undo::Refill(entries));
+    }
+
+    fn replace(&mut self, key: K, value: V) -> Option<V> {
+        let slot = self.map.get_mut(&key)?;
+        let old = mem::replace(slot, value);
+        self.undo.push(Undo::Restore(key, old.clone()));
+        Some(old)
+    }
+
+    /// Logs where `key` sits now, returning false if it is missing.
+    fn log_move<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let after = self.map.key_before(key).cloned();
+        let key = match self.map.get_key_value(key) {
+            Some((key, _)) => key.clone(),
+            None => return false,
+        };
+        self.undo.push(Undo::Move { key, after });
+        true
+    }
+}
+
+impl<K, V, S> Drop for Transaction<'_, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        self.undo_all();
+    }
+}
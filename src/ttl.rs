@@ -0,0 +1,217 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::LruCache;
+
+/// The time source a [TtlCache] measures entry ages against.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The [Clock] backed by [Instant::now].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [Clock] that only moves when [advance](ManualClock::advance) is called.
+///
+/// Clones share the same time, so a test can keep one and hand another to the cache.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// An [LruCache] whose entries also expire a fixed time after they were inserted.
+///
+/// Expired entries are dropped lazily, when a lookup finds them. Time comes from a [Clock],
+/// the system clock unless one is given with [with_clock](TtlCache::with_clock).
+pub struct TtlCache<K, V, S = RandomState, C = SystemClock> {
+    pub(crate) cache: LruCache<K, (Instant, V), S>,
+    ttl: Duration,
+    clock: C,
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        TtlCache::with_hasher(capacity, ttl, RandomState::new())
+    }
+}
+
+impl<K, V, C> TtlCache<K, V, RandomState, C>
+where
+    K: Hash + Eq,
+    C: Clock,
+{
+    pub fn with_clock(capacity: usize, ttl: Duration, clock: C) -> Self {
+        TtlCache::with_hasher_and_clock(capacity, ttl, RandomState::new(), clock)
+    }
+}
+
+impl<K, V, S> TtlCache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn with_hasher(capacity: usize, ttl: Duration, hasher: S) -> Self {
+        TtlCache::with_hasher_and_clock(capacity, ttl, hasher, SystemClock)
+    }
+}
+
+impl<K, V, S, C> TtlCache<K, V, S, C>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+    C: Clock,
+{
+    pub fn with_hasher_and_clock(capacity: usize, ttl: Duration, hasher: S, clock: C) -> Self {
+        TtlCache {
+            cache: LruCache::with_hasher(capacity, hasher),
+            ttl,
+            clock,
+        }
+    }
+
+    #[inline]
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Calls `listener` with every entry evicted to make room. Expired entries are not reported.
+    pub fn set_eviction_listener<F>(&mut self, mut listener: F)
+    where
+        F: FnMut(&K, &V) + Send + Sync + 'static,
+    {
+        self.cache
+            .set_eviction_listener(move |k, (_, v)| listener(k, v));
+    }
+
+    #[inline]
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    /// Changes the capacity, evicting least recently used entries until the cache fits.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.cache.set_capacity(capacity)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.cache.shrink_to_fit()
+    }
+
+    /// Number of stored entries, including expired ones that have not been looked up yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Returns the value for `key` if it has not expired, marking it as most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.age(key)? >= self.ttl {
+            self.cache.remove(key);
+            return None;
+        }
+        self.cache.get(key).map(|(_, v)| v)
+    }
+
+    /// Time since `key` was inserted, whether or not it has expired.
+    pub fn age<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let inserted_at = self.cache.peek(key)?.0;
+        Some(self.clock.now().saturating_duration_since(inserted_at))
+    }
+
+    #[inline]
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.age(key).is_some_and(|age| age < self.ttl)
+    }
+
+    /// Inserts `value` with a fresh expiry, returning the entry evicted to make room for it.
+    ///
+    /// A full cache first drops its least recently used expired entry, if it has one, so live
+    /// entries are only evicted when nothing has expired. That search is linear in the number
+    /// of entries.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let now = self.clock.now();
+        if self.cache.len() >= self.cache.capacity() && !self.cache.contains(&key) {
+            let ttl = self.ttl;
+            self.cache.remove_first(|_, (inserted_at, _)| {
+                now.saturating_duration_since(*inserted_at) >= ttl
+            });
+        }
+        self.cache
+            .insert(key, (now, value))
+            .map(|(k, (_, v))| (k, v))
+    }
+
+    #[inline]
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.remove(key).map(|(k, (_, v))| (k, v))
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.cache.clear()
+    }
+}
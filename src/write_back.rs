@@ -0,0 +1,349 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{IterMut, LinkedHashMap};
+
+/// The backing store a [WriteBackCache] writes dirty entries to.
+pub trait Flusher<K, V> {
+    type Error;
+
+    fn flush(&mut self, key: &K, value: &V) -> Result<(), Self::Error>;
+}
+
+impl<K, V, F> Flusher<K, V> for &mut F
+where
+    F: Flusher<K, V> + ?Sized,
+{
+    type Error = F::Error;
+
+    fn flush(&mut self, key: &K, value: &V) -> Result<(), Self::Error> {
+        (**self).flush(key, value)
+    }
+}
+
+/// A [Flusher] and [Store](crate::Store) that keeps everything written to it in memory.
+#[derive(Default)]
+pub struct MemoryStore<K, V> {
+    pub(crate) map: LinkedHashMap<K, V>,
+}
+
+impl<K, V> MemoryStore<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new() -> Self {
+        MemoryStore {
+            map: LinkedHashMap::new(),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Everything written so far, in the order keys were first written.
+    pub fn into_inner(self) -> LinkedHashMap<K, V> {
+        self.map
+    }
+}
+
+impl<K, V> Flusher<K, V> for MemoryStore<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    type Error = Infallible;
+
+    fn flush(&mut self, key: &K, value: &V) -> Result<(), Self::Error> {
+        self.map.insert(key.clone(), value.clone());
+        Ok(())
+    }
+}
+
+struct Slot<V> {
+    value: V,
+    dirty: bool,
+}
+
+/// An LRU cache that defers writes to a backing store.
+///
+/// Values inserted with [insert](WriteBackCache::insert) or changed through
+/// [get_mut](WriteBackCache::get_mut) are dirty until they are flushed. A dirty entry is written
+/// to the [Flusher] before eviction drops it, and [flush_all](WriteBackCache::flush_all) writes
+/// the remaining ones in least to most recently used order. If a write fails the entry stays
+/// cached and dirty, and the error is returned. An insert whose eviction fails still caches the
+/// new value, leaving the cache over capacity until a later insert or
+/// [set_capacity](WriteBackCache::set_capacity) evicts successfully.
+///
+/// Dirty entries still cached when the cache is dropped are not written.
+///
+/// ```rust
+/// use linked_hash_map_rs::{MemoryStore, WriteBackCache};
+///
+/// let mut store = MemoryStore::new();
+/// let mut cache = WriteBackCache::new(1, &mut store);
+/// cache.insert(1, "a").unwrap();
+/// cache.insert(2, "b").unwrap();
+/// cache.flush_all().unwrap();
+/// drop(cache);
+/// assert_eq!(store.get(&1), Some(&"a"));
+/// assert_eq!(store.get(&2), Some(&"b"));
+/// ```
+pub struct WriteBackCache<K, V, F, S = RandomState> {
+    map: LinkedHashMap<K, Slot<V>, S>,
+    capacity: usize,
+    flusher: F,
+}
+
+impl<K, V, F> WriteBackCache<K, V, F>
+where
+    K: Hash + Eq,
+    F: Flusher<K, V>,
+{
+    pub fn new(capacity: usize, flusher: F) -> Self {
+        WriteBackCache::with_hasher(capacity, flusher, RandomState::new())
+    }
+}
+
+impl<K, V, F, S> WriteBackCache<K, V, F, S>
+where
+    K: Hash + Eq,
+    F: Flusher<K, V>,
+    S: BuildHasher,
+{
+    pub fn with_hasher(capacity: usize, flusher: F, hasher: S) -> Self {
+        WriteBackCache {
+            map: LinkedHashMap::with_hasher(hasher),
+            capacity,
+            flusher,
+        }
+    }
+
+    #[inline]
+    pub fn flusher(&self) -> &F {
+        &self.flusher
+    }
+
+    #[inline]
+    pub fn flusher_mut(&mut self) -> &mut F {
+        &mut self.flusher
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, flushing and evicting least recently used entries until the
+    /// cache fits.
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), F::Error> {
+        self.capacity = capacity;
+        while self.map.len() > self.capacity {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Number of entries waiting to be flushed.
+    pub fn dirty_len(&self) -> usize {
+        self.map.iter().filter(|(_, slot)| slot.dirty).count()
+    }
+
+    #[inline]
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains(key)
+    }
+
+    pub fn is_dirty<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key).is_some_and(|slot| slot.dirty)
+    }
+
+    /// Returns the value for `key` and marks it as most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.move_to_back(key).map(|(_, slot)| &slot.value)
+    }
+
+    /// Returns the value for `key`, marking it as most recently used and as dirty.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.move_to_back(key)?;
+        self.map.get_mut(key).map(|slot| {
+            slot.dirty = true;
+            &mut slot.value
+        })
+    }
+
+    /// Returns the value for `key` without touching the recency order.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key).map(|slot| &slot.value)
+    }
+
+    /// Inserts a dirty value as the most recently used entry, flushing the entry evicted to make
+    /// room if it is dirty. The value is cached even if that flush fails.
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), F::Error> {
+        self.insert_slot(key, Slot { value, dirty: true })
+    }
+
+    /// Inserts a value that already matches the backing store, e.g. one just read from it.
+    ///
+    /// Replacing a dirty value this way discards the pending write.
+    pub fn insert_clean(&mut self, key: K, value: V) -> Result<(), F::Error> {
+        self.insert_slot(
+            key,
+            Slot {
+                value,
+                dirty: false,
+            },
+        )
+    }
+
+    fn insert_slot(&mut self, key: K, slot: Slot<V>) -> Result<(), F::Error> {
+        if let Some(old) = self.map.get_mut(&key) {
+            *old = slot;
+            self.map.move_to_back(&key);
+            return Ok(());
+        }
+        // Cache the new entry before evicting, so a failing flush cannot lose it. With a zero
+        // capacity it is the entry evicted.
+        self.map.push_back(key, slot);
+        while self.map.len() > self.capacity {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// Appends an entry as the most recently used without evicting or flushing anything.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, key: K, value: V, dirty: bool) {
+        self.map.remove(&key);
+        self.map.push_back(key, Slot { value, dirty });
+    }
+
+    fn evict(&mut self) -> Result<(), F::Error> {
+        if let Some((key, slot)) = self.map.front() {
+            if slot.dirty {
+                self.flusher.flush(key, &slot.value)?;
+            }
+        }
+        self.map.pop_front();
+        Ok(())
+    }
+
+    /// Removes `key` without flushing it, discarding any pending write.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.remove(key).map(|(k, slot)| (k, slot.value))
+    }
+
+    /// Flushes `key` if it is dirty. Returns whether anything was written.
+    pub fn flush<Q>(&mut self, key: &Q) -> Result<bool, F::Error>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.map.get_key_value_mut(key) {
+            Some((key, slot)) if slot.dirty => {
+                self.flusher.flush(key, &slot.value)?;
+                slot.dirty = false;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Flushes every dirty entry from the least to the most recently used, stopping at the
+    /// first error.
+    pub fn flush_all(&mut self) -> Result<(), F::Error> {
+        self.flush_dirty_iter()
+            .try_for_each(|result| result.map(drop))
+    }
+
+    /// Flushes dirty entries one at a time, from the least to the most recently used, yielding
+    /// each entry once it is written. Dropping the iterator early leaves the rest dirty.
+    pub fn flush_dirty_iter(&mut self) -> FlushDirty<'_, K, V, F> {
+        FlushDirty {
+            iter: self.map.iter_mut(),
+            flusher: &mut self.flusher,
+        }
+    }
+
+    /// Empties the cache without flushing, discarding all pending writes.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Iterates from the least to the most recently used entry, with each entry's dirty flag.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V, bool)> + '_ {
+        self.map
+            .iter()
+            .map(|(k, slot)| (k, &slot.value, slot.dirty))
+    }
+}
+
+/// Iterator returned by [WriteBackCache::flush_dirty_iter].
+pub struct FlushDirty<'a, K, V, F> {
+    iter: IterMut<'a, K, Slot<V>>,
+    flusher: &'a mut F,
+}
+
+impl<'a, K, V, F> Iterator for FlushDirty<'a, K, V, F>
+where
+    F: Flusher<K, V>,
+{
+    type Item = Result<(&'a K, &'a V), F::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, slot) = self.iter.find(|(_, slot)| slot.dirty)?;
+        if let Err(err) = self.flusher.flush(key, &slot.value) {
+            return Some(Err(err));
+        }
+        slot.dirty = false;
+        Some(Ok((key, &slot.value)))
+    }
+}